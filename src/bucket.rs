@@ -1,39 +1,116 @@
 use pin_project_lite::pin_project;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
 use std::future::Future;
+use std::hash::Hash;
 use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
 use sled::Transactional;
 
-use crate::{Error, Key, Raw, Transaction, TransactionError, Value};
+use crate::config::Limits;
+use crate::transaction::Recorder;
+use crate::{
+    BufferedWatch, Chunked, CoalescedWatch, Error, Key, MappedBucket, Overflow, Raw, Snapshot,
+    Transaction, TransactionError, Value, Versioned, Write,
+};
 
 /// Provides typed access to the key/value store
 #[derive(Clone)]
 pub struct Bucket<'a, K: Key<'a>, V: Value>(
     pub(crate) sled::Tree,
+    pub(crate) Limits,
     PhantomData<K>,
     PhantomData<V>,
     PhantomData<&'a ()>,
 );
 
+/// A `Bucket` with owned keys, which can be cloned into handlers or stored in shared application
+/// state without carrying a lifetime parameter around
+pub type OwnedBucket<K, V> = Bucket<'static, K, V>;
+
 /// Key/value pair
 #[derive(Clone)]
-pub struct Item<K, V>(Raw, Raw, PhantomData<K>, PhantomData<V>);
+pub struct Item<K, V>(
+    pub(crate) Raw,
+    pub(crate) Raw,
+    pub(crate) PhantomData<K>,
+    pub(crate) PhantomData<V>,
+);
+
+/// Outcome of a single attempt made by `Bucket::transaction_with_retry`
+enum Retry<E> {
+    Conflict,
+    Abort(E),
+}
 
 /// Batch update
 #[derive(Clone)]
-pub struct Batch<K, V>(pub(crate) sled::Batch, PhantomData<K>, PhantomData<V>);
+pub struct Batch<K, V>(
+    pub(crate) BTreeMap<Raw, Option<Raw>>,
+    PhantomData<K>,
+    PhantomData<V>,
+);
+
+/// A single operation applied by `Bucket::apply_ops`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op<K, V> {
+    /// Set the value associated with a key
+    Set(K, V),
+    /// Remove a key
+    Remove(K),
+    /// Replace the value associated with `key` with `new` if the current value matches `old`,
+    /// `None` means the key is missing or should be removed
+    Cas {
+        /// Key to update
+        key: K,
+        /// Expected current value
+        old: Option<V>,
+        /// New value
+        new: Option<V>,
+    },
+}
 
 pin_project! {
     /// Subscribe to key updated
     pub struct Watch<K, V> {
         #[pin]
         subscriber: sled::Subscriber,
+        exact: Option<Raw>,
         phantom: PhantomData<(K, V)>
     }
 }
 
+fn matches_exact(exact: &Option<Raw>, event: &sled::Event) -> bool {
+    let key = match event {
+        sled::Event::Insert { key, .. } | sled::Event::Remove { key } => key,
+    };
+    match exact {
+        Some(k) => k == key,
+        None => true,
+    }
+}
+
+/// Describes a difference between two buckets, see `Bucket::diff`
+pub enum Diff<K, V> {
+    /// The key only exists in the bucket `diff` was called on
+    OnlyInSelf(Item<K, V>),
+    /// The key only exists in the other bucket
+    OnlyInOther(Item<K, V>),
+    /// The key exists in both buckets with different values
+    Changed {
+        /// Raw key
+        key: Raw,
+        /// Value in the bucket `diff` was called on
+        self_value: V,
+        /// Value in the other bucket
+        other_value: V,
+    },
+}
+
 /// Event is used to describe the type of update
 pub enum Event<K, V> {
     /// A key has been updated
@@ -42,8 +119,27 @@ pub enum Event<K, V> {
     Remove(Raw),
 }
 
-impl<'a, K: Key<'a>, V> Event<K, V> {
-    fn from_sled(event: sled::Event) -> Self {
+/// An `Event` with the key and value already decoded, see `Event::into_decoded`
+///
+/// Unlike `Event` this does not borrow from itself when accessing the key, so it can easily be
+/// sent to another thread or stored in a queue
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedEvent<K, V> {
+    /// Updated key
+    pub key: K,
+    /// New value, `None` when the key has been removed
+    pub value: Option<V>,
+}
+
+impl<K, V> DecodedEvent<K, V> {
+    /// Returns true when the key has been removed
+    pub fn is_remove(&self) -> bool {
+        self.value.is_none()
+    }
+}
+
+impl<K, V> Event<K, V> {
+    pub(crate) fn from_sled(event: sled::Event) -> Self {
         match event {
             sled::Event::Insert { key, value } => {
                 Event::Set(Item(key, value, PhantomData, PhantomData))
@@ -57,9 +153,11 @@ impl<'a, K: Key<'a>, V> Iterator for Watch<K, V> {
     type Item = Result<Event<K, V>, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.subscriber.next() {
-            None => None,
-            Some(e) => Some(Ok(Event::from_sled(e))),
+        loop {
+            let e = self.subscriber.next()?;
+            if matches_exact(&self.exact, &e) {
+                return Some(Ok(Event::from_sled(e)));
+            }
         }
     }
 }
@@ -67,10 +165,13 @@ impl<'a, K: Key<'a>, V> Iterator for Watch<K, V> {
 impl<'a, K: Key<'a>, V> Future for Watch<K, V> {
     type Output = Option<Event<K, V>>;
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let this = self.project();
-        match this.subscriber.poll(cx) {
-            Poll::Pending => Poll::Pending,
-            Poll::Ready(r) => Poll::Ready(r.map(Event::from_sled)),
+        let mut this = self.project();
+        loop {
+            match this.subscriber.as_mut().poll(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Some(e)) if !matches_exact(this.exact, &e) => continue,
+                Poll::Ready(r) => return Poll::Ready(r.map(Event::from_sled)),
+            }
         }
     }
 }
@@ -103,6 +204,22 @@ impl<'a, K: Key<'a>, V: Value> Event<K, V> {
     }
 }
 
+impl<K: for<'x> Key<'x>, V: Value> Event<K, V> {
+    /// Decode the key and value, returning an owned event
+    pub fn into_decoded(self) -> Result<DecodedEvent<K, V>, Error> {
+        match self {
+            Event::Remove(k) => Ok(DecodedEvent {
+                key: K::from_raw_key(&k)?,
+                value: None,
+            }),
+            Event::Set(item) => Ok(DecodedEvent {
+                key: K::from_raw_key(&item.0)?,
+                value: Some(V::from_raw_value(item.1)?),
+            }),
+        }
+    }
+}
+
 impl<'a, K: Key<'a>, V: Value> Item<K, V> {
     /// Get the value associated with the specified key
     pub fn value<T: From<V>>(&'a self) -> Result<T, Error> {
@@ -110,6 +227,15 @@ impl<'a, K: Key<'a>, V: Value> Item<K, V> {
         Ok(x.into())
     }
 
+    /// Get the value associated with the specified key, using a fallible conversion
+    pub fn try_value<T: TryFrom<V>>(&'a self) -> Result<T, Error>
+    where
+        T::Error: std::fmt::Debug,
+    {
+        let x = V::from_raw_value(self.1.clone())?;
+        T::try_from(x).map_err(|e| Error::Conversion(format!("{:?}", e)))
+    }
+
     /// Get the value associated with the specified key
     pub fn key<T>(&'a self) -> Result<T, Error>
     where
@@ -120,6 +246,16 @@ impl<'a, K: Key<'a>, V: Value> Item<K, V> {
     }
 }
 
+impl<'a, K: Key<'a>, V: Value> Diff<K, V> {
+    /// Get the key that differs
+    pub fn key(&'a self) -> Result<K, Error> {
+        match self {
+            Diff::OnlyInSelf(item) | Diff::OnlyInOther(item) => item.key(),
+            Diff::Changed { key, .. } => K::from_raw_key(key),
+        }
+    }
+}
+
 /// Iterator over Bucket keys and values
 pub struct Iter<K, V>(sled::Iter, PhantomData<K>, PhantomData<V>);
 
@@ -154,18 +290,20 @@ where
 }
 
 impl<'a, K: Key<'a>, V: Value> Bucket<'a, K, V> {
-    pub(crate) fn new(t: sled::Tree) -> Bucket<'a, K, V> {
-        Bucket(t, PhantomData, PhantomData, PhantomData)
+    pub(crate) fn new(t: sled::Tree, limits: Limits) -> Bucket<'a, K, V> {
+        Bucket(t, limits, PhantomData, PhantomData, PhantomData)
     }
 
     /// Returns true if the bucket contains the given key
     pub fn contains(&self, key: &K) -> Result<bool, Error> {
+        self.1.ops.get();
         let v = self.0.contains_key(key.to_raw_key()?)?;
         Ok(v)
     }
 
     /// Get the value associated with the specified key
     pub fn get(&self, key: &K) -> Result<Option<V>, Error> {
+        self.1.ops.get();
         let v = self.0.get(key.to_raw_key()?)?;
 
         match v {
@@ -174,15 +312,92 @@ impl<'a, K: Key<'a>, V: Value> Bucket<'a, K, V> {
         }
     }
 
+    /// Get the values associated with `keys`, keys that don't exist are left out of the map
+    pub fn get_map(&self, keys: &[K]) -> Result<HashMap<K, V>, Error>
+    where
+        K: Eq + Hash + Clone,
+    {
+        let mut map = HashMap::with_capacity(keys.len());
+        for key in keys {
+            if let Some(v) = self.get(key)? {
+                map.insert(key.clone(), v);
+            }
+        }
+        Ok(map)
+    }
+
+    /// Get the value associated with the specified key, decoded as `U` instead of `V`
+    pub fn get_as<U: Value>(&self, key: &K) -> Result<Option<U>, Error> {
+        self.1.ops.get();
+        match self.0.get(key.to_raw_key()?)? {
+            None => Ok(None),
+            Some(x) => Ok(Some(U::from_raw_value(x)?)),
+        }
+    }
+
     /// Set the value associated with the specified key to the provided value
     pub fn set(&self, key: &K, value: &V) -> Result<Option<V>, Error> {
+        let k = key.to_raw_key()?;
         let v = value.to_raw_value()?;
-        Ok(self
-            .0
-            .insert(key.to_raw_key()?, v)?
+        self.1.check(&k, Some(&v))?;
+        self.1.ops.set();
+        self.0
+            .insert(k, v)?
             .map(|x| V::from_raw_value(x))
             // https://users.rust-lang.org/t/convenience-method-for-flipping-option-result-to-result-option/13695/7
-            .map_or(Ok(None), |v| v.map(Some))?)
+            .map_or(Ok(None), |v| v.map(Some))
+    }
+
+    /// Set the value associated with the specified key to the bytes written by `f` into a buffer
+    /// with `size` bytes preallocated. sled has no way to reserve space in place, but this avoids
+    /// building the value separately and then encoding it.
+    pub fn insert_writer(
+        &self,
+        key: &K,
+        size: usize,
+        f: impl FnOnce(&mut Vec<u8>) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let mut buf = Vec::with_capacity(size);
+        f(&mut buf)?;
+        let k = key.to_raw_key()?;
+        self.1.check(&k, Some(&buf))?;
+        self.0.insert(k, buf)?;
+        self.1.ops.set();
+        Ok(())
+    }
+
+    /// Store a large value read from `r`, split into chunks of `CHUNK_SIZE` bytes, returning
+    /// the number of bytes written
+    ///
+    /// The chunks are stored under `key/<generation><chunk index>` and a manifest under `key`, so
+    /// these keys should not be accessed using `get` or `set`. See `Chunked` for details.
+    ///
+    /// Each chunk and the manifest are checked against `Config::max_key_bytes` and
+    /// `Config::max_value_bytes`. If `key` is written by another call while the chunks are being
+    /// written, the other write is kept, this call's chunks are removed and
+    /// `Error::CompareAndSwap` is returned.
+    pub fn set_large<R: std::io::Read>(&self, key: &K, r: R) -> Result<u64, Error> {
+        let n = crate::chunked::write(&self.0, &self.1, key.to_raw_key()?, r)?;
+        self.1.ops.set();
+        Ok(n)
+    }
+
+    /// Get a reader over a value stored using `set_large`
+    pub fn get_large(&self, key: &K) -> Result<Option<Chunked>, Error> {
+        self.1.ops.get();
+        crate::chunked::open(&self.0, key.to_raw_key()?)
+    }
+
+    /// Set the value associated with the specified key to the provided value if it passes
+    /// `validate`, otherwise nothing is written and `Error::Validation` is returned
+    pub fn set_validated(
+        &self,
+        key: &K,
+        value: &V,
+        validate: impl Fn(&V) -> Result<(), String>,
+    ) -> Result<Option<V>, Error> {
+        validate(value).map_err(Error::Validation)?;
+        self.set(key, value)
     }
 
     /// Set the value associated with the specified key to the provided value, only if the existing
@@ -203,19 +418,183 @@ impl<'a, K: Key<'a>, V: Value> Bucket<'a, K, V> {
             None => None,
         };
 
-        let a = self.0.compare_and_swap(key.to_raw_key()?, old, value)?;
+        let key = key.to_raw_key()?;
+        self.1.check(&key, value.as_deref())?;
+        let removed = value.is_none();
+        self.0.compare_and_swap(key, old, value)??;
+        self.1.ops.write(removed);
+        Ok(())
+    }
+
+    /// Replace the value associated with an existing key, returning the old value or
+    /// `Error::NotFound` if the key does not exist
+    pub fn replace(&self, key: &K, value: &V) -> Result<V, Error> {
+        let key = key.to_raw_key()?;
+        let value = value.to_raw_value()?;
+        self.1.check(&key, Some(&value))?;
+        let mut current = self.0.get(&key)?;
+        loop {
+            let old = current.clone().ok_or(Error::NotFound)?;
+            match self
+                .0
+                .compare_and_swap(&key, Some(&old), Some(value.clone()))?
+            {
+                Ok(()) => {
+                    self.1.ops.set();
+                    return V::from_raw_value(old);
+                }
+                Err(e) => current = e.current,
+            }
+        }
+    }
+
+    /// Atomically replace the value associated with the specified key with the result of calling
+    /// `f` on the current value, removing the key if `f` returns `None`. `f` is called again
+    /// whenever the value is changed concurrently, so it should be free of side effects. Returns
+    /// the value that was committed.
+    pub fn compare_and_compute(
+        &self,
+        key: &K,
+        f: impl Fn(Option<&V>) -> Option<V>,
+    ) -> Result<Option<V>, Error> {
+        let key = key.to_raw_key()?;
+        let mut current = self.0.get(&key)?;
+        loop {
+            let old = match &current {
+                Some(x) => Some(V::from_raw_value(x.clone())?),
+                None => None,
+            };
+            let new = f(old.as_ref());
+            let raw = match &new {
+                Some(x) => Some(x.to_raw_value()?),
+                None => None,
+            };
+            self.1.check(&key, raw.as_deref())?;
+
+            match self.0.compare_and_swap(&key, current, raw)? {
+                Ok(()) => {
+                    self.1.ops.write(new.is_none());
+                    return Ok(new);
+                }
+                Err(e) => current = e.current,
+            }
+        }
+    }
+
+    /// Atomically replace the value associated with `key` with the result of `f`, which is
+    /// called with the current value and may be called more than once if the key is changed
+    /// concurrently. Returning `None` removes the key. Returns the new value
+    ///
+    /// If the current value can't be decoded, or the new value can't be encoded, the value is
+    /// left unchanged and the error is returned.
+    pub fn update_and_fetch<F: FnMut(Option<V>) -> Option<V>>(
+        &self,
+        key: &K,
+        mut f: F,
+    ) -> Result<Option<V>, Error> {
+        let key = key.to_raw_key()?;
+        let mut result = Ok(None);
+        self.0.update_and_fetch(&key, |old| {
+            let mut update = || -> Result<(Option<V>, Option<Raw>), Error> {
+                let old = old.map(|x| V::from_raw_value(x.into())).transpose()?;
+                let new = f(old);
+                let raw = new.as_ref().map(|x| x.to_raw_value()).transpose()?;
+                self.1.check(&key, raw.as_deref())?;
+                Ok((new, raw))
+            };
+            match update() {
+                Ok((new, raw)) => {
+                    result = Ok(new);
+                    raw
+                }
+                Err(e) => {
+                    result = Err(e);
+                    old.map(Raw::from)
+                }
+            }
+        })?;
+        if let Ok(new) = &result {
+            self.1.ops.write(new.is_none());
+        }
+        result
+    }
+
+    /// Set the value associated with the specified key only if the key is not already present.
+    /// Returns true if this call performed the write, so concurrent initializers agree on a
+    /// single winner.
+    pub fn init_once(&self, key: &K, value: &V) -> Result<bool, Error> {
+        let key = key.to_raw_key()?;
+        let value = value.to_raw_value()?;
+        self.1.check(&key, Some(&value))?;
+        let a = self
+            .0
+            .compare_and_swap(key, None as Option<Raw>, Some(value))?;
+        if a.is_ok() {
+            self.1.ops.set();
+        }
+        Ok(a.is_ok())
+    }
+
+    /// Insert each key/value pair only if the key is not already present, returning the number of
+    /// pairs that were inserted. Each key is inserted atomically using `init_once`, but the pairs
+    /// are not inserted as a single unit.
+    pub fn upsert_missing(&self, items: &[(K, V)]) -> Result<usize, Error> {
+        let mut n = 0;
+        for (k, v) in items {
+            if self.init_once(k, v)? {
+                n += 1;
+            }
+        }
+        Ok(n)
+    }
+
+    /// Get the value associated with the specified key, atomically storing and returning
+    /// `V::default()` if the key is not already present
+    pub fn get_or_set_default(&self, key: &K) -> Result<V, Error>
+    where
+        V: Default,
+    {
+        let key = key.to_raw_key()?;
+        let default = V::default().to_raw_value()?;
+        self.1.check(&key, Some(&default))?;
+        loop {
+            match self
+                .0
+                .compare_and_swap(&key, None as Option<Raw>, Some(default.clone()))?
+            {
+                Ok(()) => {
+                    self.1.ops.set();
+                    return V::from_raw_value(default);
+                }
+                Err(sled::CompareAndSwapError {
+                    current: Some(current),
+                    ..
+                }) => return V::from_raw_value(current),
+                Err(_) => continue,
+            }
+        }
+    }
 
-        Ok(a?)
+    /// Insert a value using a newly generated `Ulid` key, returning the key
+    #[cfg(feature = "ulid")]
+    pub fn push_ulid(&self, value: &V) -> Result<crate::Ulid, Error> {
+        let key = crate::Ulid::new()?;
+        let value = value.to_raw_value()?;
+        self.1.check(key.as_ref(), Some(&value))?;
+        self.0.insert(key, value)?;
+        self.1.ops.set();
+        Ok(key)
     }
 
     /// Remove the value associated with the specified key from the database
     pub fn remove(&self, key: &K) -> Result<Option<V>, Error> {
-        Ok(self
-            .0
+        self.1.writable()?;
+        self.1.ops.remove();
+        self.0
             .remove(key.to_raw_key()?)?
             .map(|x| V::from_raw_value(x))
             // https://users.rust-lang.org/t/convenience-method-for-flipping-option-result-to-result-option/13695/7
-            .map_or(Ok(None), |v| v.map(Some))?)
+            .map_or(Ok(None), |v| v.map(Some))
     }
 
     /// Get an iterator over keys/values
@@ -230,15 +609,143 @@ impl<'a, K: Key<'a>, V: Value> Bucket<'a, K, V> {
         Ok(Iter(self.0.range(a..b), PhantomData, PhantomData))
     }
 
+    /// Iterate over keys/values from `start` to the end of the bucket, `start` itself is only
+    /// included when `inclusive` is true. This can be used to resume iteration from a cursor
+    pub fn iter_from(&self, start: &K, inclusive: bool) -> Iter<K, V> {
+        let start = if inclusive {
+            Bound::Included(start.as_ref())
+        } else {
+            Bound::Excluded(start.as_ref())
+        };
+        Iter(
+            self.0.range::<&[u8], _>((start, Bound::Unbounded)),
+            PhantomData,
+            PhantomData,
+        )
+    }
+
     /// Iterate over keys/values with the specified prefix
     pub fn iter_prefix(&self, a: &K) -> Result<Iter<K, V>, Error> {
         let a = a.to_raw_key()?;
         Ok(Iter(self.0.scan_prefix(a), PhantomData, PhantomData))
     }
 
+    /// Iterate over the keys/values that start with any of `prefixes`, in key order
+    ///
+    /// Each item is returned once, even when it matches more than one prefix
+    pub fn iter_prefixes(
+        &self,
+        prefixes: &[K],
+    ) -> Result<impl Iterator<Item = Result<Item<K, V>, Error>>, Error> {
+        let mut raw = prefixes
+            .iter()
+            .map(|p| p.to_raw_key())
+            .collect::<Result<Vec<_>, _>>()?;
+        raw.sort();
+        // After sorting, a prefix that extends another one directly follows it
+        raw.dedup_by(|p, q| p.starts_with(q));
+
+        let tree = self.0.clone();
+        Ok(raw
+            .into_iter()
+            .flat_map(move |p| Iter(tree.scan_prefix(p), PhantomData, PhantomData)))
+    }
+
+    /// Copy every key/value into `dest` using a single batch, returning the number of items
+    /// copied. When `clear` is true `dest` is cleared first, otherwise existing keys in `dest` are
+    /// overwritten and other keys are kept
+    pub fn copy_to(&self, dest: &Bucket<'a, K, V>, clear: bool) -> Result<usize, Error> {
+        dest.1.writable()?;
+        let mut batch = sled::Batch::default();
+        let mut n = 0;
+        for kv in self.0.iter() {
+            let (k, v) = kv?;
+            dest.1.check(&k, Some(&v))?;
+            batch.insert(k, v);
+            n += 1;
+        }
+        if clear {
+            dest.0.clear()?;
+            dest.1.ops.remove();
+        }
+        dest.0.apply_batch(batch)?;
+        dest.1.ops.batch(n as u64, 0);
+        Ok(n)
+    }
+
+    /// Sum the values of all keys starting with `prefix`
+    pub fn sum_prefix<N: std::iter::Sum<V>>(&self, prefix: &K) -> Result<N, Error> {
+        self.0
+            .scan_prefix(prefix.to_raw_key()?)
+            .values()
+            .map(|v| V::from_raw_value(v?))
+            .sum::<Result<N, Error>>()
+    }
+
+    /// Estimate the memory needed to hold the decoded values of all keys starting with
+    /// `prefix`, by summing `Value::decoded_size_hint`
+    pub fn estimated_memory_prefix(&self, prefix: &K) -> Result<usize, Error> {
+        let mut total = 0;
+        for v in self.0.scan_prefix(prefix.to_raw_key()?).values() {
+            total += V::from_raw_value(v?)?.decoded_size_hint();
+        }
+        Ok(total)
+    }
+
+    /// Iterate over keys/values until `f` returns false, the remaining items are not read
+    pub fn iter_take_while<F: Fn(&Item<K, V>) -> bool>(
+        &self,
+        f: F,
+    ) -> impl Iterator<Item = Result<Item<K, V>, Error>> {
+        self.iter().take_while(move |item| match item {
+            Ok(item) => f(item),
+            Err(_) => true,
+        })
+    }
+
+    /// Iterate over keys/values, skipping items until `f` returns false
+    pub fn iter_skip_while<F: Fn(&Item<K, V>) -> bool>(
+        &self,
+        f: F,
+    ) -> impl Iterator<Item = Result<Item<K, V>, Error>> {
+        self.iter().skip_while(move |item| match item {
+            Ok(item) => f(item),
+            Err(_) => false,
+        })
+    }
+
+    /// Iterate over the values of all keys with the specified prefix, skipping key decoding
+    pub fn values_prefix(&self, a: &K) -> Result<impl Iterator<Item = Result<V, Error>>, Error> {
+        let a = a.to_raw_key()?;
+        Ok(self.0.scan_prefix(a).values().map(|v| match v {
+            Ok(v) => V::from_raw_value(v),
+            Err(e) => Err(e.into()),
+        }))
+    }
+
+    /// Get a read-only view of the bucket that applies `f` to values as they are read
+    pub fn map<U, F: Fn(V) -> U>(&self, f: F) -> MappedBucket<'a, K, V, U, F> {
+        MappedBucket::new(Bucket::new(self.0.clone(), self.1.clone()), f)
+    }
+
+    /// Take a snapshot of the bucket, see `Snapshot` for details
+    pub fn read_snapshot(&self) -> Result<Snapshot<K, V>, Error> {
+        Snapshot::new(&self.0)
+    }
+
     /// Apply batch update
     pub fn batch(&self, batch: Batch<K, V>) -> Result<(), Error> {
-        self.0.apply_batch(batch.0)?;
+        self.1.writable()?;
+        let mut removes = 0;
+        for (k, v) in &batch.0 {
+            self.1.check(k, v.as_deref())?;
+            if v.is_none() {
+                removes += 1;
+            }
+        }
+        let sets = batch.0.len() as u64 - removes;
+        self.0.apply_batch(batch.to_sled())?;
+        self.1.ops.batch(sets, removes);
         Ok(())
     }
 
@@ -251,6 +758,71 @@ impl<'a, K: Key<'a>, V: Value> Bucket<'a, K, V> {
         let subscriber = self.0.watch_prefix(k);
         Ok(Watch {
             subscriber,
+            exact: None,
+            phantom: PhantomData {},
+        })
+    }
+
+    /// Get the current items with the given prefix along with a `Watch` for subsequent updates
+    ///
+    /// The watch is registered before the bucket is scanned, so no update is lost between the
+    /// two: an update made while `observe` runs will show up in the returned items, in the
+    /// watch, or in both. Consumers should treat events from the watch as overriding the items.
+    #[allow(clippy::type_complexity)]
+    pub fn observe(&self, prefix: Option<&K>) -> Result<(Vec<Item<K, V>>, Watch<K, V>), Error> {
+        let k = match prefix {
+            Some(k) => k.to_raw_key()?,
+            None => b"".into(),
+        };
+        let watch = Watch {
+            subscriber: self.0.watch_prefix(k.clone()),
+            exact: None,
+            phantom: PhantomData {},
+        };
+        let items =
+            Iter(self.0.scan_prefix(k), PhantomData, PhantomData).collect::<Result<_, _>>()?;
+        Ok((items, watch))
+    }
+
+    /// Get a summary of the updates to keys with the given prefix, coalescing all events
+    /// received within `window` of the first one
+    pub fn watch_prefix_coalesced(
+        &self,
+        prefix: Option<&K>,
+        window: Duration,
+    ) -> Result<CoalescedWatch<K>, Error> {
+        let k = match prefix {
+            Some(k) => k.to_raw_key()?,
+            None => b"".into(),
+        };
+        Ok(CoalescedWatch::new(self.0.watch_prefix(k), window))
+    }
+
+    /// Get updates when a key with the given prefix is changed through a buffer holding at most
+    /// `capacity` events, `overflow` controls what happens to new events when it is full
+    ///
+    /// Returns `Error::InvalidConfiguration` if `capacity` is 0
+    pub fn watch_prefix_buffered(
+        &self,
+        prefix: Option<&K>,
+        capacity: usize,
+        overflow: Overflow,
+    ) -> Result<BufferedWatch<K, V>, Error> {
+        let k = match prefix {
+            Some(k) => k.to_raw_key()?,
+            None => b"".into(),
+        };
+        BufferedWatch::new(self.0.watch_prefix(k), capacity, overflow)
+    }
+
+    /// Get updates when the given key is changed, unlike `watch_prefix` keys that only start
+    /// with `key` are ignored
+    pub fn watch_key(&self, key: &K) -> Result<Watch<K, V>, Error> {
+        let k = key.to_raw_key()?;
+        let subscriber = self.0.watch_prefix(&k);
+        Ok(Watch {
+            subscriber,
+            exact: Some(k),
             phantom: PhantomData {},
         })
     }
@@ -258,22 +830,331 @@ impl<'a, K: Key<'a>, V: Value> Bucket<'a, K, V> {
     /// Execute a transaction
     pub fn transaction<
         A,
-        E: From<sled::Error>,
+        E: From<sled::Error> + From<Error>,
         F: Fn(Transaction<K, V>) -> Result<A, TransactionError<E>>,
     >(
         &self,
         f: F,
     ) -> Result<A, E> {
+        let attempts = self.1.attempts();
         let result = self.0.transaction(|t| {
+            attempts.next()?;
             let txn = Transaction::new(t);
             f(txn)
         });
 
-        match result {
-            Ok(x) => Ok(x),
-            Err(sled::transaction::TransactionError::Abort(x)) => Err(x),
-            Err(sled::transaction::TransactionError::Storage(e)) => Err(e.into()),
+        transaction_result(result)
+    }
+
+    /// Execute a transaction, returning the writes made using `Transaction::set`,
+    /// `Transaction::remove` and `Transaction::update` along with the result once it has been
+    /// committed. When sled retries the transaction only the writes from the final attempt are
+    /// returned.
+    pub fn transaction_with_writes<
+        A,
+        E: From<sled::Error> + From<Error>,
+        F: Fn(Transaction<K, V>) -> Result<A, TransactionError<E>>,
+    >(
+        &self,
+        f: F,
+    ) -> Result<(A, Vec<Write>), E> {
+        let recorder = Recorder::new(self.0.name());
+        let attempts = self.1.attempts();
+        let result = self.0.transaction(|t| {
+            attempts.next()?;
+            recorder.clear();
+            f(Transaction::recorded(t, &recorder))
+        });
+
+        transaction_result(result).map(|x| (x, recorder.into_writes()))
+    }
+
+    /// Execute a transaction, returning the IDs created using `Transaction::generate_id` along
+    /// with the result once it has been committed. When sled retries the transaction only the
+    /// IDs from the final attempt are returned.
+    pub fn transaction_with_ids<
+        A,
+        E: From<sled::Error> + From<Error>,
+        F: Fn(Transaction<K, V>) -> Result<A, TransactionError<E>>,
+    >(
+        &self,
+        f: F,
+    ) -> Result<(A, Vec<u64>), E> {
+        let recorder = Recorder::new(self.0.name());
+        let attempts = self.1.attempts();
+        let result = self.0.transaction(|t| {
+            attempts.next()?;
+            recorder.clear();
+            f(Transaction::recorded(t, &recorder))
+        });
+
+        transaction_result(result).map(|x| (x, recorder.into_ids()))
+    }
+
+    /// Execute a transaction, giving up with `Error::TransactionRetryExhausted` once the closure
+    /// has been attempted `max_attempts` times due to conflicts. Between attempts the calling
+    /// thread sleeps for `backoff`, doubling after each conflict.
+    ///
+    /// Each attempt runs as a separate sled transaction and the sleep happens between them, so
+    /// other transactions are not blocked while backing off.
+    pub fn transaction_with_retry<
+        A,
+        E: From<sled::Error> + From<Error>,
+        F: Fn(Transaction<K, V>) -> Result<A, TransactionError<E>>,
+    >(
+        &self,
+        max_attempts: usize,
+        backoff: Duration,
+        f: F,
+    ) -> Result<A, E> {
+        self.1.writable()?;
+        self.1.ops.transaction();
+        for n in 0..max_attempts {
+            if n > 0 {
+                self.1.ops.conflict();
+                std::thread::sleep(backoff.saturating_mul(1 << (n - 1).min(16)));
+            }
+
+            // Conflicts abort the sled transaction instead of letting sled retry it, including
+            // when sled itself calls the closure again
+            let called = Cell::new(false);
+            let result = self.0.transaction(|t| {
+                if called.replace(true) {
+                    return Err(TransactionError::Abort(Retry::Conflict));
+                }
+                match f(Transaction::new(t)) {
+                    Ok(x) => Ok(x),
+                    Err(TransactionError::Abort(e)) => {
+                        Err(TransactionError::Abort(Retry::Abort(e)))
+                    }
+                    Err(TransactionError::Conflict) => {
+                        Err(TransactionError::Abort(Retry::Conflict))
+                    }
+                    Err(TransactionError::Storage(e)) => Err(TransactionError::Storage(e)),
+                }
+            });
+
+            match result {
+                Ok(x) => return Ok(x),
+                Err(sled::transaction::TransactionError::Abort(Retry::Conflict)) => continue,
+                Err(sled::transaction::TransactionError::Abort(Retry::Abort(x))) => return Err(x),
+                Err(sled::transaction::TransactionError::Storage(e)) => return Err(e.into()),
+            }
         }
+        Err(Error::TransactionRetryExhausted.into())
+    }
+
+    /// Execute a transaction with read-only access to a snapshot of `reference`
+    ///
+    /// The snapshot is taken once before the transaction starts and is not part of the
+    /// transaction, so writes to `reference` made concurrently with the transaction are not
+    /// visible and will not cause it to conflict or retry.
+    pub fn transaction_with_snapshot<
+        A,
+        T: Key<'a>,
+        U: Value,
+        E: From<sled::Error> + From<Error>,
+        F: Fn(Transaction<K, V>, &Snapshot<T, U>) -> Result<A, TransactionError<E>>,
+    >(
+        &self,
+        reference: &Bucket<'a, T, U>,
+        f: F,
+    ) -> Result<A, E> {
+        let snapshot = reference.read_snapshot()?;
+        let attempts = self.1.attempts();
+        let result = self.0.transaction(|t| {
+            attempts.next()?;
+            let txn = Transaction::new(t);
+            f(txn, &snapshot)
+        });
+
+        transaction_result(result)
+    }
+
+    /// Remove all items with the specified prefix in a single transaction, returning the number
+    /// of items removed
+    ///
+    /// The keys are read before the transaction starts and then removed atomically. Keys with
+    /// the prefix that are added after they have been read are not removed, and keys that were
+    /// removed in the meantime are not counted. Nothing is retried: sled 0.34 runs transactions
+    /// one at a time, so concurrent writes can't conflict with this one.
+    pub fn transaction_clear_prefix(&self, prefix: &K) -> Result<usize, Error> {
+        let keys = self
+            .0
+            .scan_prefix(prefix.to_raw_key()?)
+            .keys()
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let attempts = self.1.attempts();
+        let result = self.0.transaction(|t| {
+            attempts.next()?;
+            let mut n = 0;
+            for k in &keys {
+                if t.remove(k)?.is_some() {
+                    n += 1;
+                }
+            }
+            Ok::<_, TransactionError<Error>>(n)
+        });
+
+        transaction_result(result)
+    }
+
+    /// Atomically move the value stored under `from` to `to`, returning true if the key was
+    /// renamed. Nothing is changed if `from` does not exist, or if `to` already exists and
+    /// `overwrite` is false.
+    pub fn rename_key(&self, from: &K, to: &K, overwrite: bool) -> Result<bool, Error> {
+        let from = from.to_raw_key()?;
+        let to = to.to_raw_key()?;
+
+        let attempts = self.1.attempts();
+        let result = self.0.transaction(|t| {
+            attempts.next()?;
+            let value = match t.get(&from)? {
+                Some(v) => v,
+                None => return Ok(false),
+            };
+            if !overwrite && t.get(&to)?.is_some() {
+                return Ok(false);
+            }
+            self.1
+                .check(&to, Some(&value))
+                .map_err(TransactionError::Abort)?;
+            t.remove(&from)?;
+            t.insert(&to, value)?;
+            Ok::<_, TransactionError<Error>>(true)
+        });
+
+        transaction_result(result)
+    }
+
+    /// Atomically read the values for `key` in this bucket and `other_key` in `other`,
+    /// transform them using `f` and write the results back, removing keys that `f` maps to
+    /// `None`. Returns the new values
+    #[allow(clippy::type_complexity)]
+    pub fn update2<T: Key<'a>, U: Value, F: Fn(Option<V>, Option<U>) -> (Option<V>, Option<U>)>(
+        &self,
+        other: &Bucket<'a, T, U>,
+        key: &K,
+        other_key: &T,
+        f: F,
+    ) -> Result<(Option<V>, Option<U>), Error> {
+        let key = key.to_raw_key()?;
+        let other_key = other_key.to_raw_key()?;
+        let attempts = self.1.attempts();
+        let result = (&self.0, &other.0).transaction(|(a, b)| {
+            attempts.next()?;
+            let abort = TransactionError::Abort;
+            let x = a
+                .get(&key)?
+                .map(V::from_raw_value)
+                .transpose()
+                .map_err(abort)?;
+            let y = b
+                .get(&other_key)?
+                .map(U::from_raw_value)
+                .transpose()
+                .map_err(abort)?;
+            let (x, y) = f(x, y);
+            match &x {
+                Some(v) => {
+                    let v = v.to_raw_value().map_err(abort)?;
+                    self.1.check(&key, Some(&v)).map_err(abort)?;
+                    a.insert(&key, v)?;
+                }
+                None => {
+                    a.remove(&key)?;
+                }
+            }
+            match &y {
+                Some(v) => {
+                    let v = v.to_raw_value().map_err(abort)?;
+                    other.1.check(&other_key, Some(&v)).map_err(abort)?;
+                    b.insert(&other_key, v)?;
+                }
+                None => {
+                    b.remove(&other_key)?;
+                }
+            }
+            Ok::<_, TransactionError<Error>>((x, y))
+        });
+
+        transaction_result(result)
+    }
+
+    /// Apply `ops` in order in a single transaction. If the current value doesn't match the
+    /// expected value of an `Op::Cas` the transaction is aborted with `Error::CompareAndSwap`
+    /// and none of the operations are applied
+    pub fn apply_ops(&self, ops: Vec<Op<K, V>>) -> Result<(), Error> {
+        let encode = |v: Option<V>| v.map(|v| v.to_raw_value()).transpose();
+        let mut raw = Vec::with_capacity(ops.len());
+        for op in ops {
+            let (key, old, new) = match op {
+                Op::Set(k, v) => (k.to_raw_key()?, None, Some(v.to_raw_value()?)),
+                Op::Remove(k) => (k.to_raw_key()?, None, None),
+                Op::Cas { key, old, new } => (key.to_raw_key()?, Some(encode(old)?), encode(new)?),
+            };
+            self.1.check(&key, new.as_deref())?;
+            raw.push((key, old, new));
+        }
+
+        let attempts = self.1.attempts();
+        let result = self.0.transaction(|t| {
+            attempts.next()?;
+            for (key, old, new) in &raw {
+                if let Some(old) = old {
+                    let current = t.get(key)?;
+                    if current != *old {
+                        return Err(TransactionError::Abort(Error::CompareAndSwap(
+                            sled::CompareAndSwapError {
+                                current,
+                                proposed: new.clone(),
+                            },
+                        )));
+                    }
+                }
+                match new {
+                    Some(v) => t.insert(key, v.clone())?,
+                    None => t.remove(key)?,
+                };
+            }
+            Ok::<_, TransactionError<Error>>(())
+        });
+
+        transaction_result(result)
+    }
+
+    /// Apply `batch` in a single transaction if the value associated with `cond_key` matches
+    /// `expected`, `None` means the key must be missing. Returns true if the batch was applied
+    pub fn apply_batch_if(
+        &self,
+        batch: Batch<K, V>,
+        cond_key: &K,
+        expected: Option<&V>,
+    ) -> Result<bool, Error> {
+        self.1.writable()?;
+        for (k, v) in &batch.0 {
+            self.1.check(k, v.as_deref())?;
+        }
+        let cond_key = cond_key.to_raw_key()?;
+        let expected = expected.map(|v| v.to_raw_value()).transpose()?;
+
+        let attempts = self.1.attempts();
+        let result = self.0.transaction(|t| {
+            attempts.next()?;
+            if t.get(&cond_key)? != expected {
+                return Ok(false);
+            }
+            for (k, v) in &batch.0 {
+                match v {
+                    Some(v) => t.insert(k, v.clone())?,
+                    None => t.remove(k)?,
+                };
+            }
+            Ok::<_, TransactionError<Error>>(true)
+        });
+
+        transaction_result(result)
     }
 
     /// Create a transaction with access to two buckets
@@ -281,24 +1162,22 @@ impl<'a, K: Key<'a>, V: Value> Bucket<'a, K, V> {
         A,
         T: Key<'a>,
         U: Value,
-        E: From<sled::Error>,
+        E: From<sled::Error> + From<Error>,
         F: Fn(Transaction<K, V>, Transaction<T, U>) -> Result<A, TransactionError<E>>,
     >(
         &self,
         other: &Bucket<'a, T, U>,
         f: F,
     ) -> Result<A, E> {
+        let attempts = self.1.attempts();
         let result = (&self.0, &other.0).transaction(|(a, b)| {
+            attempts.next()?;
             let a = Transaction::new(a);
             let b = Transaction::new(b);
             f(a, b)
         });
 
-        match result {
-            Ok(x) => Ok(x),
-            Err(sled::transaction::TransactionError::Abort(x)) => Err(x),
-            Err(sled::transaction::TransactionError::Storage(e)) => Err(e.into()),
-        }
+        transaction_result(result)
     }
 
     /// Create a transaction with access to three buckets
@@ -308,7 +1187,7 @@ impl<'a, K: Key<'a>, V: Value> Bucket<'a, K, V> {
         U: Value,
         X: Key<'a>,
         Y: Value,
-        E: From<sled::Error>,
+        E: From<sled::Error> + From<Error>,
         F: Fn(
             Transaction<K, V>,
             Transaction<T, U>,
@@ -320,18 +1199,16 @@ impl<'a, K: Key<'a>, V: Value> Bucket<'a, K, V> {
         other1: &Bucket<'a, X, Y>,
         f: F,
     ) -> Result<A, E> {
+        let attempts = self.1.attempts();
         let result = (&self.0, &other.0, &other1.0).transaction(|(a, b, c)| {
+            attempts.next()?;
             let a = Transaction::new(a);
             let b = Transaction::new(b);
             let c = Transaction::new(c);
             f(a, b, c)
         });
 
-        match result {
-            Ok(x) => Ok(x),
-            Err(sled::transaction::TransactionError::Abort(x)) => Err(x),
-            Err(sled::transaction::TransactionError::Storage(e)) => Err(e.into()),
-        }
+        transaction_result(result)
     }
 
     /// Get previous key and value in order, if one exists
@@ -346,6 +1223,54 @@ impl<'a, K: Key<'a>, V: Value> Bucket<'a, K, V> {
         Ok(item.map(|(k, v)| Item(k, v, PhantomData, PhantomData)))
     }
 
+    /// Compute the key-level differences between this bucket and `other`, ordered by key
+    pub fn diff(&self, other: &Bucket<'a, K, V>) -> Result<Vec<Diff<K, V>>, Error> {
+        let mut diff = Vec::new();
+        let mut a = self.0.iter();
+        let mut b = other.0.iter();
+        let mut x = a.next().transpose()?;
+        let mut y = b.next().transpose()?;
+
+        loop {
+            match (x.take(), y.take()) {
+                (None, None) => break,
+                (Some((k, v)), None) => {
+                    diff.push(Diff::OnlyInSelf(Item(k, v, PhantomData, PhantomData)));
+                    x = a.next().transpose()?;
+                }
+                (None, Some((k, v))) => {
+                    diff.push(Diff::OnlyInOther(Item(k, v, PhantomData, PhantomData)));
+                    y = b.next().transpose()?;
+                }
+                (Some((k0, v0)), Some((k1, v1))) => match k0.cmp(&k1) {
+                    std::cmp::Ordering::Less => {
+                        diff.push(Diff::OnlyInSelf(Item(k0, v0, PhantomData, PhantomData)));
+                        x = a.next().transpose()?;
+                        y = Some((k1, v1));
+                    }
+                    std::cmp::Ordering::Greater => {
+                        diff.push(Diff::OnlyInOther(Item(k1, v1, PhantomData, PhantomData)));
+                        x = Some((k0, v0));
+                        y = b.next().transpose()?;
+                    }
+                    std::cmp::Ordering::Equal => {
+                        if v0 != v1 {
+                            diff.push(Diff::Changed {
+                                key: k0,
+                                self_value: V::from_raw_value(v0)?,
+                                other_value: V::from_raw_value(v1)?,
+                            });
+                        }
+                        x = a.next().transpose()?;
+                        y = b.next().transpose()?;
+                    }
+                },
+            }
+        }
+
+        Ok(diff)
+    }
+
     /// Flush to disk
     pub fn flush(&self) -> Result<usize, Error> {
         Ok(self.0.flush()?)
@@ -359,16 +1284,36 @@ impl<'a, K: Key<'a>, V: Value> Bucket<'a, K, V> {
 
     /// Remove and return the last item
     pub fn pop_back(&self) -> Result<Option<Item<K, V>>, Error> {
+        self.1.writable()?;
         let x = self.0.pop_max()?;
+        if x.is_some() {
+            self.1.ops.remove();
+        }
         Ok(x.map(|(k, v)| Item(k, v, PhantomData, PhantomData)))
     }
 
     /// Remove and return the first item
     pub fn pop_front(&self) -> Result<Option<Item<K, V>>, Error> {
+        self.1.writable()?;
         let x = self.0.pop_min()?;
+        if x.is_some() {
+            self.1.ops.remove();
+        }
         Ok(x.map(|(k, v)| Item(k, v, PhantomData, PhantomData)))
     }
 
+    /// Get the last item without removing it, together with `pop_back` this allows a bucket with
+    /// increasing keys to be used as a stack
+    pub fn peek_back(&self) -> Result<Option<Item<K, V>>, Error> {
+        self.last()
+    }
+
+    /// Get the first item without removing it, together with `pop_front` this allows a bucket
+    /// with increasing keys to be used as a queue
+    pub fn peek_front(&self) -> Result<Option<Item<K, V>>, Error> {
+        self.first()
+    }
+
     /// Get the first item
     pub fn first(&self) -> Result<Option<Item<K, V>>, Error> {
         let x = self.0.first()?;
@@ -381,6 +1326,37 @@ impl<'a, K: Key<'a>, V: Value> Bucket<'a, K, V> {
         Ok(x.map(|(k, v)| Item(k, v, PhantomData, PhantomData)))
     }
 
+    /// Get a random item
+    ///
+    /// A random key is generated between the first and last keys and the next item at or after
+    /// it is returned, wrapping around to the first item. This is cheap, but only approximately
+    /// uniform: items that follow large gaps in the keyspace are more likely to be returned.
+    pub fn sample(&self) -> Result<Option<Item<K, V>>, Error> {
+        let (first, last) = match (self.0.first()?, self.0.last()?) {
+            (Some((first, _)), Some((last, _))) => (first, last),
+            _ => return Ok(None),
+        };
+
+        let prefix = first
+            .iter()
+            .zip(last.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let mut key = last[..prefix].to_vec();
+        if let Some(hi) = last.get(prefix) {
+            let lo = first.get(prefix).copied().unwrap_or(0);
+            let range = (*hi - lo) as u64 + 1;
+            key.push(lo + (random_u64() % range) as u8);
+        }
+        key.extend_from_slice(&random_u64().to_be_bytes());
+
+        let x = match self.0.range(key..).next() {
+            Some(x) => Some(x?),
+            None => self.0.first()?,
+        };
+        Ok(x.map(|(k, v)| Item(k, v, PhantomData, PhantomData)))
+    }
+
     /// Get the number of items
     pub fn len(&self) -> usize {
         self.0.len()
@@ -391,9 +1367,21 @@ impl<'a, K: Key<'a>, V: Value> Bucket<'a, K, V> {
         self.0.is_empty()
     }
 
+    /// Count the items in the specified range without decoding them
+    pub fn count_range<R: RangeBounds<K>>(&self, range: R) -> Result<usize, Error> {
+        let mut n = 0;
+        for k in self.0.range(raw_bounds(&range)?).keys() {
+            k?;
+            n += 1;
+        }
+        Ok(n)
+    }
+
     /// Remove all items
     pub fn clear(&self) -> Result<(), Error> {
+        self.1.writable()?;
         self.0.clear()?;
+        self.1.ops.remove();
         Ok(())
     }
 
@@ -403,6 +1391,333 @@ impl<'a, K: Key<'a>, V: Value> Bucket<'a, K, V> {
     }
 }
 
+impl<'a, K: for<'x> Key<'x>, V: Value> Bucket<'a, K, V> {
+    /// Rewrite every key using `f`, returning the number of keys that changed
+    ///
+    /// The new keys are computed up front and then written in a single transaction, so readers
+    /// see either the old or the new keys. Fails without changing anything if two keys map to the
+    /// same new key. Writes made to the bucket while the keys are being computed may be lost.
+    pub fn rekey<F: Fn(&K) -> Result<K, Error>>(&self, f: F) -> Result<usize, Error> {
+        let mut moves = BTreeMap::new();
+        for kv in self.0.iter() {
+            let (k, v) = kv?;
+            let new = f(&K::from_raw_key(&k)?)?.to_raw_key()?;
+            self.1.check(&new, Some(&v))?;
+            if let Some((old, _)) = moves.insert(new.clone(), (k.clone(), v)) {
+                return Err(Error::Message(format!(
+                    "rekey collision: {:?} and {:?} both map to {:?}",
+                    old, k, new
+                )));
+            }
+        }
+        moves.retain(|new, (old, _)| new != old);
+
+        let attempts = self.1.attempts();
+        let result = self.0.transaction(|t| {
+            attempts.next()?;
+            for (old, _) in moves.values() {
+                t.remove(old)?;
+            }
+            for (new, (_, v)) in &moves {
+                t.insert(new, v)?;
+            }
+            Ok::<_, TransactionError<Error>>(moves.len())
+        });
+
+        transaction_result(result)
+    }
+
+    /// Collect every key, in order, stopping at the first error
+    pub fn key_vec(&self) -> Result<Vec<K>, Error> {
+        self.0.iter().keys().map(|k| K::from_raw_key(&k?)).collect()
+    }
+
+    /// Merge-join this bucket with `other` by key, walking both buckets in key order and
+    /// yielding each key along with its value in either bucket. This can be used for inner,
+    /// left, right and full outer joins by filtering the rows. Iteration stops after the first
+    /// error
+    #[allow(clippy::type_complexity)]
+    pub fn join<V2: Value>(
+        &self,
+        other: &Bucket<'a, K, V2>,
+    ) -> impl Iterator<Item = Result<(K, Option<V>, Option<V2>), Error>> {
+        let mut a = self.0.iter().fuse();
+        let mut b = other.0.iter().fuse();
+        let mut x = None;
+        let mut y = None;
+        let mut failed = false;
+
+        std::iter::from_fn(move || {
+            if failed {
+                return None;
+            }
+            let mut next = || -> Result<Option<(K, Option<V>, Option<V2>)>, Error> {
+                if x.is_none() {
+                    x = a.next().transpose()?;
+                }
+                if y.is_none() {
+                    y = b.next().transpose()?;
+                }
+                let (k, v0, v1) = match (x.take(), y.take()) {
+                    (None, None) => return Ok(None),
+                    (Some((k, v)), None) => (k, Some(v), None),
+                    (None, Some((k, v))) => (k, None, Some(v)),
+                    (Some((k0, v0)), Some((k1, v1))) => match k0.cmp(&k1) {
+                        std::cmp::Ordering::Less => {
+                            y = Some((k1, v1));
+                            (k0, Some(v0), None)
+                        }
+                        std::cmp::Ordering::Greater => {
+                            x = Some((k0, v0));
+                            (k1, None, Some(v1))
+                        }
+                        std::cmp::Ordering::Equal => (k0, Some(v0), Some(v1)),
+                    },
+                };
+                Ok(Some((
+                    K::from_raw_key(&k)?,
+                    v0.map(V::from_raw_value).transpose()?,
+                    v1.map(V2::from_raw_value).transpose()?,
+                )))
+            };
+            let row = next().transpose();
+            failed = matches!(row, Some(Err(_)));
+            row
+        })
+    }
+
+    /// Collect the keys/values in the specified range into a map
+    pub fn range_map<R: RangeBounds<K>>(&self, range: R) -> Result<BTreeMap<K, V>, Error>
+    where
+        K: Ord,
+    {
+        let mut map = BTreeMap::new();
+        for item in self.0.range(raw_bounds(&range)?) {
+            let (k, v) = item?;
+            map.insert(K::from_raw_key(&k)?, V::from_raw_value(v)?);
+        }
+        Ok(map)
+    }
+
+    /// Iterate over the items whose key and value can be decoded, skipping the rest
+    ///
+    /// `on_error` is called with the raw key and the error for each item that is skipped. Errors
+    /// reading from the database end the iteration and are reported without a key.
+    pub fn iter_lossy<F: FnMut(Option<Raw>, Error)>(
+        &self,
+        mut on_error: F,
+    ) -> impl Iterator<Item = Item<K, V>> {
+        let mut iter = self.0.iter();
+        std::iter::from_fn(move || loop {
+            let (k, v) = match iter.next()? {
+                Ok(kv) => kv,
+                Err(e) => {
+                    on_error(None, e.into());
+                    return None;
+                }
+            };
+
+            let decoded = K::from_raw_key(&k).and_then(|_| V::from_raw_value(v.clone()));
+            match decoded {
+                Ok(_) => return Some(Item(k, v, PhantomData, PhantomData)),
+                Err(e) => on_error(Some(k), e),
+            }
+        })
+    }
+
+    /// Get up to `limit` items with the specified prefix that come after the key `after`, along
+    /// with the key to pass as `after` to get the next page, or `None` if there are no more items
+    ///
+    /// Returns an error if `limit` is 0, since an empty page has no key to continue from
+    #[allow(clippy::type_complexity)]
+    pub fn page_prefix(
+        &self,
+        prefix: &K,
+        after: Option<&K>,
+        limit: usize,
+    ) -> Result<(Vec<Item<K, V>>, Option<K>), Error> {
+        if limit == 0 {
+            return Err(Error::Message("Page limit must be greater than 0".into()));
+        }
+
+        let prefix = prefix.to_raw_key()?;
+        let start = match after {
+            Some(k) => match k.to_raw_key()? {
+                k if k.as_ref() >= prefix.as_ref() => Bound::Excluded(k),
+                _ => Bound::Included(prefix.clone()),
+            },
+            None => Bound::Included(prefix.clone()),
+        };
+
+        let mut items = Vec::with_capacity(limit);
+        let mut more = false;
+        for kv in self.0.range::<Raw, _>((start, Bound::Unbounded)) {
+            let (k, v) = kv?;
+            if !k.starts_with(&prefix) {
+                break;
+            }
+            if items.len() == limit {
+                more = true;
+                break;
+            }
+            items.push(Item(k, v, PhantomData, PhantomData));
+        }
+
+        let cursor = match items.last() {
+            Some(item) if more => Some(K::from_raw_key(&item.0)?),
+            _ => None,
+        };
+        Ok((items, cursor))
+    }
+
+    /// Get up to `n - 1` keys that split the bucket into `n` ranges containing a similar number of
+    /// items, each returned key is the first key of the next range
+    pub fn split_points(&self, n: usize) -> Result<Vec<K>, Error> {
+        let len = self.0.len();
+        let mut points = Vec::new();
+        if n < 2 || len == 0 {
+            return Ok(points);
+        }
+
+        let mut next = 1;
+        for (i, k) in self.0.iter().keys().enumerate() {
+            if next >= n {
+                break;
+            }
+
+            let k = k?;
+            if i > 0 && i >= next * len / n {
+                points.push(K::from_raw_key(&k)?);
+                while next < n && i >= next * len / n {
+                    next += 1;
+                }
+            }
+        }
+
+        Ok(points)
+    }
+
+    /// Write all items to `w` as CSV, returning the number of rows written
+    ///
+    /// The first row is a header containing `key` followed by the field names of the value type
+    /// (or `value` if it is not a struct). Values must be flat: nested structs, sequences and
+    /// maps result in an error.
+    #[cfg(feature = "csv")]
+    pub fn export_csv<W: std::io::Write>(&self, w: W) -> Result<usize, Error>
+    where
+        K: serde::Serialize,
+        V: serde::Serialize,
+    {
+        let mut w = csv::WriterBuilder::new().has_headers(false).from_writer(w);
+        let mut count = 0;
+        for kv in self.0.iter() {
+            let (k, v) = kv?;
+            let k = K::from_raw_key(&k)?;
+            let v = V::from_raw_value(v)?;
+
+            if count == 0 {
+                // Writing a header fails for nested values, the header itself is taken from the
+                // first of the two rows output for structs
+                let mut h = csv::Writer::from_writer(Vec::new());
+                h.serialize(&v)?;
+                let h = h.into_inner().map_err(|e| Error::IO(e.into_error()))?;
+                let rows = csv::ReaderBuilder::new()
+                    .has_headers(false)
+                    .from_reader(h.as_slice())
+                    .into_records()
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut header = vec!["key".to_string()];
+                if rows.len() > 1 {
+                    header.extend(rows[0].iter().map(String::from));
+                } else {
+                    header.push("value".to_string());
+                }
+                w.write_record(&header)?;
+            }
+
+            w.serialize((k, v))?;
+            count += 1;
+        }
+        w.flush()?;
+        Ok(count)
+    }
+}
+
+impl<'a, K: Key<'a>, T: Value> Bucket<'a, K, Versioned<T>> {
+    /// Set the value associated with the specified key if its stored version is
+    /// `expected_version` (missing keys have version 0), returning the new version. Fails with
+    /// `Error::VersionConflict` if the stored version is different.
+    pub fn set_versioned(&self, key: &K, value: T, expected_version: u64) -> Result<u64, Error> {
+        let key = key.to_raw_key()?;
+        let old = self.0.get(&key)?;
+        let found = match &old {
+            Some(x) => Versioned::<Raw>::from_raw_value(x.clone())?.version,
+            None => 0,
+        };
+
+        if found != expected_version {
+            return Err(Error::VersionConflict {
+                expected: expected_version,
+                found,
+            });
+        }
+
+        let version = expected_version + 1;
+        let new = Versioned { version, value }.to_raw_value()?;
+        self.1.check(&key, Some(&new))?;
+        match self.0.compare_and_swap(&key, old, Some(new))? {
+            Ok(()) => {
+                self.1.ops.set();
+                Ok(version)
+            }
+            Err(e) => Err(Error::VersionConflict {
+                expected: expected_version,
+                found: match e.current {
+                    Some(x) => Versioned::<Raw>::from_raw_value(x)?.version,
+                    None => 0,
+                },
+            }),
+        }
+    }
+}
+
+pub(crate) fn raw_bounds<'a, K: Key<'a>, R: RangeBounds<K>>(
+    range: &R,
+) -> Result<(Bound<Raw>, Bound<Raw>), Error> {
+    fn encode<'a, K: Key<'a>>(b: Bound<&K>) -> Result<Bound<Raw>, Error> {
+        Ok(match b {
+            Bound::Included(k) => Bound::Included(k.to_raw_key()?),
+            Bound::Excluded(k) => Bound::Excluded(k.to_raw_key()?),
+            Bound::Unbounded => Bound::Unbounded,
+        })
+    }
+
+    Ok((encode(range.start_bound())?, encode(range.end_bound())?))
+}
+
+/// Convert the result of a sled transaction, returning the error the closure aborted with or
+/// the storage error
+pub(crate) fn transaction_result<A, E: From<sled::Error>>(
+    result: sled::transaction::TransactionResult<A, E>,
+) -> Result<A, E> {
+    match result {
+        Ok(x) => Ok(x),
+        Err(sled::transaction::TransactionError::Abort(x)) => Err(x),
+        Err(sled::transaction::TransactionError::Storage(e)) => Err(e.into()),
+    }
+}
+
+pub(crate) fn random_u64() -> u64 {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+
+    let mut hasher = RandomState::new().build_hasher();
+    if let Ok(ts) = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+        hasher.write_u128(ts.as_nanos());
+    }
+    hasher.finish()
+}
+
 impl<'a, K: Key<'a>, V: Value> Default for Batch<K, V> {
     fn default() -> Self {
         Batch::new()
@@ -412,19 +1727,41 @@ impl<'a, K: Key<'a>, V: Value> Default for Batch<K, V> {
 impl<'a, K: Key<'a>, V: Value> Batch<K, V> {
     /// Create a new Batch instance
     pub fn new() -> Batch<K, V> {
-        Batch(sled::Batch::default(), PhantomData, PhantomData)
+        Batch(BTreeMap::new(), PhantomData, PhantomData)
     }
 
     /// Set the value associated with the specified key to the provided value
     pub fn set(&mut self, key: &K, value: &V) -> Result<(), Error> {
         let v = value.to_raw_value()?;
-        self.0.insert(key.to_raw_key()?, v);
+        self.0.insert(key.to_raw_key()?, Some(v));
         Ok(())
     }
 
     /// Remove the value associated with the specified key from the database
     pub fn remove(&mut self, key: &K) -> Result<(), Error> {
-        self.0.remove(key.to_raw_key()?);
+        self.0.insert(key.to_raw_key()?, None);
         Ok(())
     }
+
+    /// Get the value queued for the specified key, without reading from the database
+    ///
+    /// Returns `None` when the key is queued for removal or the batch contains no operation for
+    /// it
+    pub fn get(&self, key: &K) -> Result<Option<V>, Error> {
+        match self.0.get(&key.to_raw_key()?) {
+            Some(Some(v)) => Ok(Some(V::from_raw_value(v.clone())?)),
+            _ => Ok(None),
+        }
+    }
+
+    pub(crate) fn to_sled(&self) -> sled::Batch {
+        let mut batch = sled::Batch::default();
+        for (k, v) in &self.0 {
+            match v {
+                Some(v) => batch.insert(k.clone(), v.clone()),
+                None => batch.remove(k.clone()),
+            }
+        }
+        batch
+    }
 }
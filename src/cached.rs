@@ -0,0 +1,170 @@
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use lru::LruCache;
+
+use crate::{Bucket, Error, Key, Raw, Value};
+
+// How often the background thread drains the watch, sled blocks writers to the bucket once a
+// watch has 1024 undelivered events
+const DRAIN_INTERVAL: Duration = Duration::from_millis(10);
+
+// Keys changed since the cache was last accessed, shared with the background thread
+struct Changes {
+    subscriber: sled::Subscriber,
+    keys: Vec<Raw>,
+    limit: usize,
+    overflowed: bool,
+}
+
+impl Changes {
+    fn drain(&mut self) {
+        while let Ok(event) = self.subscriber.next_timeout(Duration::from_secs(0)) {
+            if self.overflowed {
+                continue;
+            }
+            match event {
+                sled::Event::Insert { key, .. } | sled::Event::Remove { key } => {
+                    self.keys.push(key)
+                }
+            }
+            // Once more keys have changed than the cache can hold it is simpler to clear it
+            if self.keys.len() > self.limit {
+                self.keys.clear();
+                self.overflowed = true;
+            }
+        }
+    }
+}
+
+struct Cache<V> {
+    values: LruCache<Raw, V>,
+    changes: Arc<Mutex<Changes>>,
+}
+
+impl<V> Cache<V> {
+    // Drop entries for keys that have been changed since the last call, including changes made
+    // through other handles to the same bucket
+    fn invalidate_changed(&mut self) -> Result<(), Error> {
+        let mut changes = self.changes.lock()?;
+        changes.drain();
+        if changes.overflowed {
+            self.values.clear();
+            changes.overflowed = false;
+        }
+        for key in changes.keys.drain(..) {
+            self.values.pop(&key);
+        }
+        Ok(())
+    }
+}
+
+/// A bucket with an in-memory LRU cache of decoded values, see `Store::cached_bucket`
+///
+/// Cached entries are invalidated by `set` and `remove`, and by any other write to the bucket,
+/// which is detected using a watch on the whole bucket when the cache is accessed. The watch is
+/// also drained periodically by a background thread, which exits after the `CachedBucket` is
+/// dropped.
+pub struct CachedBucket<'a, K: Key<'a>, V: Value + Clone> {
+    bucket: Bucket<'a, K, V>,
+    cache: Mutex<Cache<V>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<'a, K: Key<'a>, V: Value + Clone> CachedBucket<'a, K, V> {
+    pub(crate) fn new(bucket: Bucket<'a, K, V>, capacity: usize) -> Result<Self, Error> {
+        let capacity = NonZeroUsize::new(capacity).ok_or(Error::InvalidConfiguration)?;
+        let changes = Arc::new(Mutex::new(Changes {
+            subscriber: bucket.0.watch_prefix(b""),
+            keys: Vec::new(),
+            limit: capacity.get(),
+            overflowed: false,
+        }));
+        let weak = Arc::downgrade(&changes);
+        std::thread::Builder::new()
+            .name("kv-cache".into())
+            .spawn(move || loop {
+                std::thread::sleep(DRAIN_INTERVAL);
+                let changes = match weak.upgrade() {
+                    Some(changes) => changes,
+                    None => break,
+                };
+                match changes.lock() {
+                    Ok(mut changes) => changes.drain(),
+                    Err(_) => break,
+                };
+            })?;
+        Ok(CachedBucket {
+            bucket,
+            cache: Mutex::new(Cache {
+                values: LruCache::new(capacity),
+                changes,
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        })
+    }
+
+    /// Get the underlying bucket, writes made using it are also detected by the cache
+    pub fn bucket(&self) -> &Bucket<'a, K, V> {
+        &self.bucket
+    }
+
+    /// Get the value associated with the specified key, from the cache if possible
+    pub fn get(&self, key: &K) -> Result<Option<V>, Error> {
+        let k = key.to_raw_key()?;
+        let mut cache = self.cache.lock()?;
+        cache.invalidate_changed()?;
+        if let Some(v) = cache.values.get(&k) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(Some(v.clone()));
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let v = self.bucket.get(key)?;
+        if let Some(v) = &v {
+            cache.values.put(k, v.clone());
+        }
+        Ok(v)
+    }
+
+    /// Set the value associated with the specified key to the provided value
+    pub fn set(&self, key: &K, value: &V) -> Result<Option<V>, Error> {
+        self.invalidate(key)?;
+        self.bucket.set(key, value)
+    }
+
+    /// Remove the value associated with the specified key from the database
+    pub fn remove(&self, key: &K) -> Result<Option<V>, Error> {
+        self.invalidate(key)?;
+        self.bucket.remove(key)
+    }
+
+    // The lock is released before writing, since the write may have to wait for the watch to be
+    // drained
+    fn invalidate(&self, key: &K) -> Result<(), Error> {
+        let mut cache = self.cache.lock()?;
+        cache.invalidate_changed()?;
+        cache.values.pop(&key.to_raw_key()?);
+        Ok(())
+    }
+
+    /// Remove all entries from the cache
+    pub fn clear_cache(&self) -> Result<(), Error> {
+        self.cache.lock()?.values.clear();
+        Ok(())
+    }
+
+    /// Number of `get` calls answered from the cache
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Number of `get` calls that read from the bucket
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
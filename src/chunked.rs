@@ -0,0 +1,197 @@
+use std::io::{self, Read};
+
+use crate::config::Limits;
+use crate::{Error, Raw};
+
+/// Size of each chunk written by `Bucket::set_large`
+pub const CHUNK_SIZE: usize = 1024 * 1024;
+
+const MANIFEST_LEN: usize = 24;
+
+fn chunk_key(key: &[u8], generation: u64, index: u64) -> Vec<u8> {
+    let mut k = Vec::with_capacity(key.len() + 17);
+    k.extend_from_slice(key);
+    k.push(b'/');
+    k.extend_from_slice(&generation.to_be_bytes());
+    k.extend_from_slice(&index.to_be_bytes());
+    k
+}
+
+struct Manifest {
+    count: u64,
+    len: u64,
+    generation: u64,
+}
+
+fn manifest(key: &[u8], tree: &sled::Tree) -> Result<Option<Manifest>, Error> {
+    tree.get(key)?.map(|m| decode_manifest(&m)).transpose()
+}
+
+fn decode_manifest(m: &[u8]) -> Result<Manifest, Error> {
+    if m.len() != MANIFEST_LEN {
+        return Err(Error::InvalidLength {
+            expected: MANIFEST_LEN,
+            found: m.len(),
+        });
+    }
+    let field = |i: usize| {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(&m[i * 8..(i + 1) * 8]);
+        u64::from_be_bytes(buf)
+    };
+    Ok(Manifest {
+        count: field(0),
+        len: field(1),
+        generation: field(2),
+    })
+}
+
+fn remove_chunks(tree: &sled::Tree, key: &[u8], generation: u64, count: u64) -> Result<(), Error> {
+    let mut batch = sled::Batch::default();
+    for index in 0..count {
+        batch.remove(chunk_key(key, generation, index));
+    }
+    tree.apply_batch(batch)?;
+    Ok(())
+}
+
+// Write the chunks of a new value, `count` and `len` are updated as each chunk is written so the
+// caller can remove them on failure
+fn write_chunks<R: Read>(
+    tree: &sled::Tree,
+    limits: &Limits,
+    key: &[u8],
+    generation: u64,
+    mut r: R,
+    count: &mut u64,
+    len: &mut u64,
+) -> Result<(), Error> {
+    loop {
+        let mut buf = Vec::with_capacity(CHUNK_SIZE);
+        let n = (&mut r).take(CHUNK_SIZE as u64).read_to_end(&mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        let k = chunk_key(key, generation, *count);
+        limits.check(&k, Some(&buf))?;
+        tree.insert(k, buf)?;
+        *count += 1;
+        *len += n as u64;
+    }
+}
+
+pub(crate) fn write<R: Read>(
+    tree: &sled::Tree,
+    limits: &Limits,
+    key: Raw,
+    r: R,
+) -> Result<u64, Error> {
+    let current = tree.get(&key)?;
+    let old = current.as_deref().map(decode_manifest).transpose()?;
+
+    // Chunks are written under a new generation so they never replace the chunks of the value
+    // that is currently visible
+    let mut generation = crate::bucket::random_u64();
+    if let Some(old) = &old {
+        if old.generation == generation {
+            generation = generation.wrapping_add(1);
+        }
+    }
+
+    let mut count = 0;
+    let mut len = 0;
+    let written =
+        write_chunks(tree, limits, &key, generation, r, &mut count, &mut len).and_then(|()| {
+            let mut m = Vec::with_capacity(MANIFEST_LEN);
+            m.extend_from_slice(&count.to_be_bytes());
+            m.extend_from_slice(&len.to_be_bytes());
+            m.extend_from_slice(&generation.to_be_bytes());
+            limits.check(&key, Some(&m))?;
+
+            // The manifest is swapped last so partially written values are never visible. If
+            // the value was replaced in the meantime the other write wins
+            Ok(tree.compare_and_swap(&key, current, Some(m))??)
+        });
+    if let Err(e) = written {
+        remove_chunks(tree, &key, generation, count)?;
+        return Err(e);
+    }
+
+    if let Some(old) = old {
+        remove_chunks(tree, &key, old.generation, old.count)?;
+    }
+
+    Ok(len)
+}
+
+pub(crate) fn open(tree: &sled::Tree, key: Raw) -> Result<Option<Chunked>, Error> {
+    Ok(manifest(&key, tree)?.map(|m| Chunked {
+        tree: tree.clone(),
+        key,
+        generation: m.generation,
+        count: m.count,
+        len: m.len,
+        index: 0,
+        chunk: Raw::from(&b""[..]),
+        pos: 0,
+    }))
+}
+
+/// Reader over a value stored using `Bucket::set_large`
+///
+/// Values are split into `CHUNK_SIZE` chunks stored under `key/<generation><chunk index>` along
+/// with a manifest stored under `key` itself, which records the generation, the number of chunks
+/// and the total length. Chunks are loaded one at a time as the value is read, if the value is
+/// overwritten while it is being read the remaining chunks are missing and reading fails rather
+/// than returning bytes from the new value.
+pub struct Chunked {
+    tree: sled::Tree,
+    key: Raw,
+    generation: u64,
+    count: u64,
+    len: u64,
+    index: u64,
+    chunk: Raw,
+    pos: usize,
+}
+
+impl Chunked {
+    /// Total length of the value in bytes
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns true when the value is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Read for Chunked {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos >= self.chunk.len() {
+            if self.index >= self.count {
+                return Ok(0);
+            }
+
+            let k = chunk_key(&self.key, self.generation, self.index);
+            self.chunk = match self.tree.get(k) {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        format!("missing chunk {}", self.index),
+                    ))
+                }
+                Err(e) => return Err(e.into()),
+            };
+            self.index += 1;
+            self.pos = 0;
+        }
+
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
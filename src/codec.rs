@@ -39,6 +39,12 @@ macro_rules! codec {
                 $x(self.0.clone())
             }
         }
+
+        impl<T: serde::Serialize + serde::de::DeserializeOwned> serde::Serialize for $x<T> {
+            fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                self.0.serialize(s)
+            }
+        }
     };
 
     ($x:ident, {$ser:expr, $de:expr}) => {
@@ -54,10 +60,28 @@ macro_rules! codec {
                 let x = $de(&r)?;
                 Ok($x(x))
             }
+
+            /// The size of `T` plus the encoded length, as an estimate of its heap usage
+            fn decoded_size_hint(&self) -> usize {
+                let encoded = self.to_raw_value().map(|r| r.len()).unwrap_or(0);
+                std::mem::size_of::<T>() + encoded
+            }
         }
     };
 }
 
+/// Names of the codecs enabled at compile time, as used by `Config::required_codecs`
+pub const CODECS: &[&str] = &[
+    #[cfg(feature = "json-value")]
+    "json",
+    #[cfg(feature = "msgpack-value")]
+    "msgpack",
+    #[cfg(feature = "bincode-value")]
+    "bincode",
+    #[cfg(feature = "lexpr-value")]
+    "lexpr",
+];
+
 #[cfg(feature = "msgpack-value")]
 mod msgpack_value {
     use super::*;
@@ -1,12 +1,26 @@
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::{fs, io};
 
 use crate::error::Error;
+use crate::ops::OpCounters;
 use serde::{Deserialize, Serialize};
 
+/// Version of the serialized configuration format written by `Config::save`
+pub const CONFIG_VERSION: u32 = 1;
+
+fn default_version() -> u32 {
+    CONFIG_VERSION
+}
+
 /// Config is used to create a new store
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Config {
+    /// The `version` field records the configuration format, files written before it was added
+    /// are treated as the current version
+    #[serde(default = "default_version")]
+    pub version: u32,
+
     /// The `path` field determines where the database will be created
     pub path: PathBuf,
 
@@ -29,21 +43,184 @@ pub struct Config {
     /// Specify the segment size for compatibility
     #[serde(default)]
     pub segment_size: Option<usize>,
+
+    /// Names of codecs (see `CODECS`) that must be enabled for the store to open
+    #[serde(default)]
+    pub required_codecs: Vec<String>,
+
+    /// Maximum size of a key in bytes, larger keys are rejected with `Error::KeyTooLarge`
+    #[serde(default)]
+    pub max_key_bytes: Option<usize>,
+
+    /// Maximum size of a value in bytes, larger values are rejected with `Error::ValueTooLarge`
+    #[serde(default)]
+    pub max_value_bytes: Option<usize>,
+
+    /// Maximum number of times a bucket transaction is attempted before it fails with
+    /// `Error::TransactionRetryExhausted`, by default transactions are retried indefinitely
+    #[serde(default)]
+    pub max_transaction_retries: Option<u32>,
+}
+
+/// Key/value size and transaction retry limits enforced by a `Bucket`, along with the store's
+/// operation counters
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Limits {
+    key: Option<usize>,
+    value: Option<usize>,
+    transaction_retries: Option<u32>,
+    pub(crate) read_only: bool,
+    pub(crate) ops: Arc<OpCounters>,
+}
+
+/// Counts the attempts made by a transaction, see `Config::max_transaction_retries`
+pub(crate) struct Attempts<'a> {
+    ops: &'a OpCounters,
+    read_only: bool,
+    max: Option<u32>,
+    n: std::cell::Cell<u32>,
+}
+
+impl Attempts<'_> {
+    /// Called at the start of each attempt, aborts once the limit has been reached
+    pub(crate) fn next<E: From<Error>>(&self) -> Result<(), crate::TransactionError<E>> {
+        if self.read_only {
+            return Err(crate::TransactionError::Abort(Error::ReadOnly.into()));
+        }
+        let n = self.n.get();
+        if n > 0 {
+            self.ops.conflict();
+        }
+        if let Some(max) = self.max {
+            if n >= max {
+                return Err(crate::TransactionError::Abort(
+                    Error::TransactionRetryExhausted.into(),
+                ));
+            }
+        }
+        self.n.set(n + 1);
+        Ok(())
+    }
+}
+
+impl Limits {
+    pub(crate) fn attempts(&self) -> Attempts<'_> {
+        self.ops.transaction();
+        Attempts {
+            ops: &self.ops,
+            read_only: self.read_only,
+            max: self.transaction_retries,
+            n: std::cell::Cell::new(0),
+        }
+    }
+
+    pub(crate) fn writable(&self) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check(&self, key: &[u8], value: Option<&[u8]>) -> Result<(), Error> {
+        self.writable()?;
+        if let Some(limit) = self.key {
+            if key.len() > limit {
+                return Err(Error::KeyTooLarge {
+                    size: key.len(),
+                    limit,
+                });
+            }
+        }
+
+        if let (Some(limit), Some(value)) = (self.value, value) {
+            if value.len() > limit {
+                return Err(Error::ValueTooLarge {
+                    size: value.len(),
+                    limit,
+                });
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl Config {
     /// Create a default configuration object
     pub fn new<P: AsRef<Path>>(p: P) -> Config {
         Config {
+            version: CONFIG_VERSION,
             path: p.as_ref().to_path_buf(),
             temporary: false,
             use_compression: false,
             flush_every_ms: None,
             cache_capacity: None,
             segment_size: None,
+            required_codecs: Vec::new(),
+            max_key_bytes: None,
+            max_value_bytes: None,
+            max_transaction_retries: None,
         }
     }
 
+    /// Load configuration from environment variables named `{prefix}_{FIELD}`, for example
+    /// `{prefix}_PATH`, `{prefix}_USE_COMPRESSION` or `{prefix}_CACHE_CAPACITY`. Every field
+    /// except `path` is optional and falls back to its default, `required_codecs` is read as a
+    /// comma-separated list.
+    pub fn from_env(prefix: &str) -> Result<Config, Error> {
+        fn var(prefix: &str, name: &str) -> Option<String> {
+            std::env::var(format!("{}_{}", prefix, name)).ok()
+        }
+
+        fn parse<T: std::str::FromStr>(prefix: &str, name: &str) -> Result<Option<T>, Error> {
+            match var(prefix, name) {
+                None => Ok(None),
+                Some(s) => match s.trim().parse() {
+                    Ok(x) => Ok(Some(x)),
+                    Err(_) => Err(Error::Message(format!(
+                        "Invalid value for {}_{}: {}",
+                        prefix, name, s
+                    ))),
+                },
+            }
+        }
+
+        fn flag(prefix: &str, name: &str) -> Result<bool, Error> {
+            match var(prefix, name).as_deref().map(str::trim) {
+                None | Some("") | Some("0") | Some("false") => Ok(false),
+                Some("1") | Some("true") => Ok(true),
+                Some(s) => Err(Error::Message(format!(
+                    "Invalid value for {}_{}: {}",
+                    prefix, name, s
+                ))),
+            }
+        }
+
+        let path = match var(prefix, "PATH") {
+            Some(path) => path,
+            None => return Err(Error::Message(format!("{}_PATH is not set", prefix))),
+        };
+
+        let mut config = Config::new(path);
+        config.temporary = flag(prefix, "TEMPORARY")?;
+        config.use_compression = flag(prefix, "USE_COMPRESSION")?;
+        config.flush_every_ms = parse(prefix, "FLUSH_EVERY_MS")?;
+        config.cache_capacity = parse(prefix, "CACHE_CAPACITY")?;
+        config.segment_size = parse(prefix, "SEGMENT_SIZE")?;
+        config.max_key_bytes = parse(prefix, "MAX_KEY_BYTES")?;
+        config.max_value_bytes = parse(prefix, "MAX_VALUE_BYTES")?;
+        config.max_transaction_retries = parse(prefix, "MAX_TRANSACTION_RETRIES")?;
+        if let Some(codecs) = var(prefix, "REQUIRED_CODECS") {
+            config.required_codecs = codecs
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+                .collect();
+        }
+        Ok(config)
+    }
+
     /// Save Config to an io::Write
     pub fn save_to<W: io::Write>(&self, mut w: W) -> Result<(), Error> {
         let s = match toml::to_string(self) {
@@ -64,10 +241,17 @@ impl Config {
     pub fn load_from<R: io::Read>(mut r: R) -> Result<Config, Error> {
         let mut buf = Vec::new();
         r.read_to_end(&mut buf)?;
-        match toml::from_slice(buf.as_ref()) {
-            Ok(cfg) => Ok(cfg),
-            Err(_) => Err(Error::InvalidConfiguration),
+        let cfg: Config = match toml::from_slice(buf.as_ref()) {
+            Ok(cfg) => cfg,
+            Err(_) => return Err(Error::InvalidConfiguration),
+        };
+        if cfg.version != CONFIG_VERSION {
+            return Err(Error::IncompatibleConfigVersion {
+                found: cfg.version,
+                supported: CONFIG_VERSION,
+            });
         }
+        Ok(cfg)
     }
 
     /// Load configuration to a file
@@ -106,7 +290,49 @@ impl Config {
         self
     }
 
+    /// Require a codec to be enabled when opening the store
+    pub fn require_codec<S: Into<String>>(mut self, name: S) -> Config {
+        self.required_codecs.push(name.into());
+        self
+    }
+
+    /// Set the maximum key size in bytes
+    pub fn max_key_bytes(mut self, bytes: usize) -> Config {
+        self.max_key_bytes = Some(bytes);
+        self
+    }
+
+    /// Set the maximum value size in bytes
+    pub fn max_value_bytes(mut self, bytes: usize) -> Config {
+        self.max_value_bytes = Some(bytes);
+        self
+    }
+
+    /// Set the maximum number of attempts for bucket transactions
+    pub fn max_transaction_retries(mut self, attempts: u32) -> Config {
+        self.max_transaction_retries = Some(attempts);
+        self
+    }
+
+    pub(crate) fn limits(&self) -> Limits {
+        Limits {
+            key: self.max_key_bytes,
+            value: self.max_value_bytes,
+            transaction_retries: self.max_transaction_retries,
+            read_only: false,
+            ops: Arc::default(),
+        }
+    }
+
     pub(crate) fn open(&mut self) -> Result<sled::Db, Error> {
+        if let Some(name) = self
+            .required_codecs
+            .iter()
+            .find(|name| !crate::CODECS.contains(&name.as_str()))
+        {
+            return Err(Error::MissingCodec { name: name.clone() });
+        }
+
         let config = sled::Config::new()
             .path(&self.path)
             .temporary(self.temporary)
@@ -123,7 +349,18 @@ impl Config {
         } else {
             config
         };
-        let db = config.open()?;
-        Ok(db)
+        match config.open() {
+            Ok(db) => Ok(db),
+            // sled reports lock conflicts as a generic IO error
+            Err(sled::Error::Io(e))
+                if e.kind() == io::ErrorKind::Other
+                    && e.to_string().starts_with("could not acquire lock") =>
+            {
+                Err(Error::AlreadyOpen {
+                    path: self.path.clone(),
+                })
+            }
+            Err(e) => Err(e.into()),
+        }
     }
 }
@@ -0,0 +1,133 @@
+use sled::Transactional;
+
+use crate::bucket::transaction_result;
+use crate::{Bucket, Error, Iter, Key, Raw, TransactionError, Value};
+
+// Starts with `RESERVED_PREFIX` so it is hidden from `Store::buckets` and exports
+pub(crate) const COUNTS_TREE: &str = "__kv__counts";
+
+/// Recalculate the stored count for the bucket `name` if it has one, after `tree` was changed
+/// without going through a `CountedBucket`
+pub(crate) fn recount(counts: &sled::Tree, name: &[u8], tree: &sled::Tree) -> Result<(), Error> {
+    if counts.contains_key(name)? {
+        counts.insert(name, &(tree.len() as u64).to_be_bytes())?;
+    }
+    Ok(())
+}
+
+/// A bucket that keeps track of the number of items it contains, see `Store::counted_bucket`
+///
+/// The item count is stored in a reserved tree and updated in the same transaction as every
+/// `set` and `remove`, which makes `len` O(1) at the cost of a transaction per write. Only
+/// operations that keep the count accurate are exposed.
+///
+/// `Store` methods that rewrite whole buckets, such as `swap_buckets`, `drop_bucket`,
+/// `merge_from` and the imports, update the count too. Writes made through a plain `Bucket`
+/// opened on the same tree are not counted, and a count recalculated while a `CountedBucket`
+/// is writing to the bucket may be off by the writes made in the meantime.
+#[derive(Clone)]
+pub struct CountedBucket<'a, K: Key<'a>, V: Value> {
+    bucket: Bucket<'a, K, V>,
+    counts: sled::Tree,
+    name: Raw,
+}
+
+fn decode_count(x: Option<Raw>) -> u64 {
+    match x {
+        Some(x) if x.len() == 8 => {
+            let mut buf = [0u8; 8];
+            buf.copy_from_slice(&x);
+            u64::from_be_bytes(buf)
+        }
+        _ => 0,
+    }
+}
+
+impl<'a, K: Key<'a>, V: Value> CountedBucket<'a, K, V> {
+    pub(crate) fn new(
+        bucket: Bucket<'a, K, V>,
+        counts: sled::Tree,
+        name: Raw,
+    ) -> Result<Self, Error> {
+        if !counts.contains_key(&name)? {
+            let n = bucket.0.len() as u64;
+            let _ = counts.compare_and_swap(
+                &name,
+                None as Option<Raw>,
+                Some(Raw::from(&n.to_be_bytes())),
+            )?;
+        }
+
+        Ok(CountedBucket {
+            bucket,
+            counts,
+            name,
+        })
+    }
+
+    fn update(&self, key: &K, value: Option<&V>) -> Result<Option<V>, Error> {
+        let key = key.to_raw_key()?;
+        let value = match value {
+            Some(v) => Some(v.to_raw_value()?),
+            None => None,
+        };
+        self.bucket.1.check(&key, value.as_deref())?;
+
+        let attempts = self.bucket.1.attempts();
+        let result = (&self.bucket.0, &self.counts).transaction(|(tree, counts)| {
+            attempts.next()?;
+            let old = match &value {
+                Some(v) => tree.insert(&key, v)?,
+                None => tree.remove(&key)?,
+            };
+
+            let n = decode_count(counts.get(&self.name)?);
+            let n = match (&old, &value) {
+                (None, Some(_)) => n + 1,
+                (Some(_), None) => n.saturating_sub(1),
+                _ => n,
+            };
+            counts.insert(&self.name, &n.to_be_bytes())?;
+            Ok::<_, TransactionError<Error>>(old)
+        });
+
+        transaction_result(result)?
+            .map(V::from_raw_value)
+            .transpose()
+    }
+
+    /// Returns true if the bucket contains the given key
+    pub fn contains(&self, key: &K) -> Result<bool, Error> {
+        self.bucket.contains(key)
+    }
+
+    /// Get the value associated with the specified key
+    pub fn get(&self, key: &K) -> Result<Option<V>, Error> {
+        self.bucket.get(key)
+    }
+
+    /// Set the value associated with the specified key to the provided value
+    pub fn set(&self, key: &K, value: &V) -> Result<Option<V>, Error> {
+        self.update(key, Some(value))
+    }
+
+    /// Remove the value associated with the specified key from the database
+    pub fn remove(&self, key: &K) -> Result<Option<V>, Error> {
+        self.update(key, None)
+    }
+
+    /// Get an iterator over keys/values
+    pub fn iter(&self) -> Iter<K, V> {
+        self.bucket.iter()
+    }
+
+    /// Get the number of items in O(1)
+    pub fn len(&self) -> Result<usize, Error> {
+        Ok(decode_count(self.counts.get(&self.name)?) as usize)
+    }
+
+    /// Returns true when there are no items
+    pub fn is_empty(&self) -> Result<bool, Error> {
+        Ok(self.len()? == 0)
+    }
+}
@@ -0,0 +1,70 @@
+use crate::config::Limits;
+use crate::{Error, Key, Raw};
+
+/// A set of named 64-bit signed counters, see `Store::counters`
+///
+/// Counters are stored as 8 big-endian bytes and updated atomically using compare-and-swap, a
+/// counter that has never been set has the value 0.
+#[derive(Clone)]
+pub struct Counters(sled::Tree, Limits);
+
+fn decode(x: Option<&Raw>) -> Result<i64, Error> {
+    match x {
+        None => Ok(0),
+        Some(x) => {
+            let buf: [u8; 8] = x.as_ref().try_into().map_err(|_| Error::InvalidLength {
+                expected: 8,
+                found: x.len(),
+            })?;
+            Ok(i64::from_be_bytes(buf))
+        }
+    }
+}
+
+impl Counters {
+    pub(crate) fn new(tree: sled::Tree, limits: Limits) -> Counters {
+        Counters(tree, limits)
+    }
+
+    /// Get the current value of a counter
+    pub fn get<'a, K: Key<'a>>(&self, key: &K) -> Result<i64, Error> {
+        self.1.ops.get();
+        decode(self.0.get(key.to_raw_key()?)?.as_ref())
+    }
+
+    /// Add `n` to a counter, returning the new value
+    pub fn incr<'a, K: Key<'a>>(&self, key: &K, n: i64) -> Result<i64, Error> {
+        self.1.writable()?;
+        let key = key.to_raw_key()?;
+        let mut current = self.0.get(&key)?;
+        loop {
+            let value = decode(current.as_ref())?
+                .checked_add(n)
+                .ok_or_else(|| Error::Message("Counter overflow".into()))?;
+            let new = Raw::from(&value.to_be_bytes());
+            match self.0.compare_and_swap(&key, current, Some(new))? {
+                Ok(()) => {
+                    self.1.ops.set();
+                    return Ok(value);
+                }
+                Err(e) => current = e.current,
+            }
+        }
+    }
+
+    /// Subtract `n` from a counter, returning the new value
+    pub fn decr<'a, K: Key<'a>>(&self, key: &K, n: i64) -> Result<i64, Error> {
+        let n = n
+            .checked_neg()
+            .ok_or_else(|| Error::Message("Counter overflow".into()))?;
+        self.incr(key, n)
+    }
+
+    /// Reset a counter to 0, returning its previous value
+    pub fn reset<'a, K: Key<'a>>(&self, key: &K) -> Result<i64, Error> {
+        self.1.writable()?;
+        let old = self.0.remove(key.to_raw_key()?)?;
+        self.1.ops.remove();
+        decode(old.as_ref())
+    }
+}
@@ -18,10 +18,73 @@ pub enum Error {
     #[error("IO error: {0}")]
     IO(#[from] io::Error),
 
+    /// The database is already opened by another process or `Store`
+    #[error("Database is already open: {path:?}")]
+    AlreadyOpen {
+        /// Database path
+        path: std::path::PathBuf,
+    },
+
+    /// The stored version did not match the expected version
+    #[error("Version conflict: expected {expected}, found {found}")]
+    VersionConflict {
+        /// Expected version
+        expected: u64,
+        /// Stored version
+        found: u64,
+    },
+
     /// Configuration is invalid
     #[error("Configuration is invalid")]
     InvalidConfiguration,
 
+    /// Configuration was written with an unsupported format version
+    #[error("Incompatible configuration version: found {found}, supported {supported}")]
+    IncompatibleConfigVersion {
+        /// Version found in the configuration
+        found: u32,
+        /// Version supported by this library
+        supported: u32,
+    },
+
+    /// A key exceeded the configured `max_key_bytes`
+    #[error("Key too large: {size} bytes, limit is {limit}")]
+    KeyTooLarge {
+        /// Size of the key
+        size: usize,
+        /// Configured limit
+        limit: usize,
+    },
+
+    /// A value exceeded the configured `max_value_bytes`
+    #[error("Value too large: {size} bytes, limit is {limit}")]
+    ValueTooLarge {
+        /// Size of the value
+        size: usize,
+        /// Configured limit
+        limit: usize,
+    },
+
+    /// A `Checked` value did not match its stored checksum
+    #[error("Checksum mismatch: expected {expected:#010x}, found {found:#010x}")]
+    ChecksumMismatch {
+        /// Checksum stored with the value
+        expected: u32,
+        /// Checksum of the stored bytes
+        found: u32,
+    },
+
+    /// A transaction kept conflicting and reached its retry limit
+    #[error("Transaction retry limit exhausted")]
+    TransactionRetryExhausted,
+
+    /// A codec required by the configuration was not enabled at compile time
+    #[error("Codec not enabled: {name}")]
+    MissingCodec {
+        /// Codec name
+        name: String,
+    },
+
     /// RwLock is poisoned
     #[error("RwLock is poisoned")]
     Poison,
@@ -34,10 +97,44 @@ pub enum Error {
     #[error("String UTF8 error")]
     FromUtf8(std::string::FromUtf8Error),
 
+    /// A string key or value is not valid UTF8
+    #[error("Invalid UTF8 in {bytes:?}: {source}")]
+    InvalidUtf8 {
+        /// The bytes that failed to decode
+        bytes: Vec<u8>,
+        /// Underlying error
+        source: std::str::Utf8Error,
+    },
+
     /// SystemTime
     #[error("SystemTime: {0}")]
     SystemTime(#[from] std::time::SystemTimeError),
 
+    /// Encoded data has an unexpected length
+    #[error("Invalid length: expected {expected} bytes, found {found}")]
+    InvalidLength {
+        /// Expected number of bytes
+        expected: usize,
+        /// Actual number of bytes
+        found: usize,
+    },
+
+    /// A decoded value could not be converted to the requested type
+    #[error("Conversion error: {0}")]
+    Conversion(String),
+
+    /// A value was rejected by a validator
+    #[error("Validation error: {0}")]
+    Validation(String),
+
+    /// The key does not exist
+    #[error("Key not found")]
+    NotFound,
+
+    /// A write was attempted on a store opened using `Store::open_read_only`
+    #[error("Store is read-only")]
+    ReadOnly,
+
     /// Generic message
     #[error("Message: {0}")]
     Message(String),
@@ -62,6 +159,11 @@ pub enum Error {
     #[error("Bincode encoding Error: {0}")]
     Bincode(#[from] Box<bincode::ErrorKind>),
 
+    /// CSV error
+    #[cfg(feature = "csv")]
+    #[error("CSV error: {0}")]
+    Csv(#[from] csv::Error),
+
     /// Lexpr error
     #[cfg(feature = "lexpr-value")]
     #[error("S-Expression error: {0}")]
@@ -74,6 +176,14 @@ impl<T> From<PoisonError<T>> for Error {
     }
 }
 
+/// Decode `bytes` as UTF8, including the bytes in the error on failure
+pub(crate) fn utf8(bytes: &[u8]) -> Result<&str, Error> {
+    std::str::from_utf8(bytes).map_err(|source| Error::InvalidUtf8 {
+        bytes: bytes.to_vec(),
+        source,
+    })
+}
+
 impl From<std::str::Utf8Error> for Error {
     fn from(e: std::str::Utf8Error) -> Error {
         Error::Utf8(e)
@@ -1,4 +1,5 @@
-use std::mem;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
 use crate::{Error, Raw};
@@ -28,7 +29,7 @@ impl<'a> Key<'a> for &'a [u8] {
 
 impl<'a> Key<'a> for &'a str {
     fn from_raw_key(x: &'a Raw) -> Result<Self, Error> {
-        Ok(std::str::from_utf8(x.as_ref())?)
+        crate::error::utf8(x.as_ref())
     }
 }
 
@@ -38,25 +39,106 @@ impl<'a> Key<'a> for Vec<u8> {
     }
 }
 
+/// Fixed-size keys, decoding fails with `Error::InvalidLength` if the length doesn't match
+impl<'a, const N: usize> Key<'a> for [u8; N] {
+    fn from_raw_key(x: &Raw) -> Result<Self, Error> {
+        x.as_ref().try_into().map_err(|_| Error::InvalidLength {
+            expected: N,
+            found: x.len(),
+        })
+    }
+}
+
 impl<'a> Key<'a> for String {
     fn from_raw_key(x: &Raw) -> Result<Self, Error> {
-        Ok(std::str::from_utf8(x.as_ref())?.to_string())
+        Ok(crate::error::utf8(x.as_ref())?.to_string())
     }
 }
 
+/// Integer keys are 16 bytes, decoding fails with `Error::InvalidLength` for other lengths, such as
+/// the 8-byte keys written by older versions, see `Store::migrate_integer_keys`
 impl<'a> Key<'a> for Integer {
     fn from_raw_key(x: &Raw) -> Result<Integer, Error> {
-        Ok(Integer::from(x.as_ref()))
+        let buf = x.as_ref().try_into().map_err(|_| Error::InvalidLength {
+            expected: 16,
+            found: x.len(),
+        })?;
+        Ok(Integer(buf))
+    }
+}
+
+/// Bool keys are a single byte, decoding fails for bytes other than 0 and 1
+impl<'a> Key<'a> for Bool {
+    fn from_raw_key(x: &Raw) -> Result<Bool, Error> {
+        match x.as_ref() {
+            [b @ (0 | 1)] => Ok(Bool([*b])),
+            [b] => Err(Error::Conversion(format!("Invalid bool key: {}", b))),
+            _ => Err(Error::InvalidLength {
+                expected: 1,
+                found: x.len(),
+            }),
+        }
+    }
+}
+
+/// Char keys are 4 bytes, decoding fails if they don't contain a valid Unicode scalar value
+impl<'a> Key<'a> for Char {
+    fn from_raw_key(x: &Raw) -> Result<Char, Error> {
+        let buf: [u8; 4] = x.as_ref().try_into().map_err(|_| Error::InvalidLength {
+            expected: 4,
+            found: x.len(),
+        })?;
+        let n = u32::from_be_bytes(buf);
+        match char::from_u32(n) {
+            Some(_) => Ok(Char(buf)),
+            None => Err(Error::Conversion(format!("Invalid char key: {:#x}", n))),
+        }
+    }
+}
+
+impl<'a> Key<'a> for KeyPath {
+    fn from_raw_key(x: &Raw) -> Result<KeyPath, Error> {
+        let path = KeyPath(x.to_vec());
+        path.segments()?;
+        Ok(path)
+    }
+}
+
+impl<'a> Key<'a> for OsKey {
+    fn from_raw_key(x: &Raw) -> Result<OsKey, Error> {
+        #[cfg(unix)]
+        let s = <OsStr as std::os::unix::ffi::OsStrExt>::from_bytes(x.as_ref());
+        #[cfg(not(unix))]
+        let s = OsStr::new(crate::error::utf8(x.as_ref())?);
+        Ok(OsKey(s.to_os_string()))
+    }
+}
+
+#[cfg(feature = "ulid")]
+impl<'a> Key<'a> for Ulid {
+    fn from_raw_key(x: &Raw) -> Result<Ulid, Error> {
+        let buf = x.as_ref().try_into().map_err(|_| Error::InvalidLength {
+            expected: 16,
+            found: x.len(),
+        })?;
+        Ok(Ulid(buf))
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl<'a> Key<'a> for bytes::Bytes {
+    fn from_raw_key(x: &Raw) -> Result<Self, Error> {
+        Ok(bytes::Bytes::from_owner(x.clone()))
     }
 }
 
 /// Integer key type
-#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Integer([u8; 16]);
 
 impl From<u128> for Integer {
     fn from(i: u128) -> Integer {
-        unsafe { Integer(mem::transmute(i.to_be())) }
+        Integer(i.to_be_bytes())
     }
 }
 
@@ -89,14 +171,8 @@ impl From<usize> for Integer {
 }
 
 impl From<Integer> for u128 {
-    #[cfg(target_endian = "big")]
     fn from(i: Integer) -> u128 {
-        unsafe { mem::transmute(i.0) }
-    }
-
-    #[cfg(target_endian = "little")]
-    fn from(i: Integer) -> u128 {
-        u128::from_be(unsafe { mem::transmute(i.0) })
+        u128::from_be_bytes(i.0)
     }
 }
 
@@ -128,6 +204,51 @@ impl<'a> From<&'a [u8]> for Integer {
     }
 }
 
+/// Bool key type, stored as a single 0 or 1 byte so `false` is ordered before `true`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Bool([u8; 1]);
+
+impl From<bool> for Bool {
+    fn from(b: bool) -> Bool {
+        Bool([b as u8])
+    }
+}
+
+impl From<Bool> for bool {
+    fn from(b: Bool) -> bool {
+        b.0[0] != 0
+    }
+}
+
+impl AsRef<[u8]> for Bool {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Char key type, stored as the big-endian code point so keys are ordered by code point
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Char([u8; 4]);
+
+impl From<char> for Char {
+    fn from(c: char) -> Char {
+        Char((c as u32).to_be_bytes())
+    }
+}
+
+impl From<Char> for char {
+    fn from(c: Char) -> char {
+        // Only valid code points are stored, see `From<char>` and `Key::from_raw_key`
+        char::from_u32(u32::from_be_bytes(c.0)).unwrap_or(char::REPLACEMENT_CHARACTER)
+    }
+}
+
+impl AsRef<[u8]> for Char {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
 impl Integer {
     /// Current timestamp in seconds from the Unix epoch
     pub fn timestamp() -> Result<Integer, Error> {
@@ -140,4 +261,207 @@ impl Integer {
         let ts = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
         Ok(Integer::from(ts.as_millis()))
     }
+
+    /// Add `rhs`, returning `None` on overflow
+    pub fn checked_add(&self, rhs: u128) -> Option<Integer> {
+        u128::from(*self).checked_add(rhs).map(Integer::from)
+    }
+
+    /// Subtract `rhs`, returning `None` on underflow
+    pub fn checked_sub(&self, rhs: u128) -> Option<Integer> {
+        u128::from(*self).checked_sub(rhs).map(Integer::from)
+    }
+
+    /// The following key, or `None` if this is the largest value
+    pub fn next(&self) -> Option<Integer> {
+        self.checked_add(1)
+    }
+
+    /// The preceding key, or `None` if this is zero
+    pub fn prev(&self) -> Option<Integer> {
+        self.checked_sub(1)
+    }
+}
+
+/// Hierarchical key made of multiple segments
+///
+/// Each segment is terminated by `0x00 0x01` and any `0x00` bytes within a segment are escaped as
+/// `0x00 0xff`, this means segments can contain arbitrary bytes without colliding and keys are
+/// ordered segment by segment. A `KeyPath` can also be used as a prefix to iterate over all of
+/// its descendants.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct KeyPath(Vec<u8>);
+
+impl KeyPath {
+    /// Create an empty path
+    pub fn new() -> KeyPath {
+        KeyPath(Vec::new())
+    }
+
+    /// Append a segment
+    pub fn push<S: AsRef<[u8]>>(&mut self, segment: S) {
+        for b in segment.as_ref() {
+            if *b == 0 {
+                self.0.extend_from_slice(&[0, 0xff]);
+            } else {
+                self.0.push(*b);
+            }
+        }
+        self.0.extend_from_slice(&[0, 1]);
+    }
+
+    /// Append a segment and return the updated path
+    pub fn with<S: AsRef<[u8]>>(mut self, segment: S) -> KeyPath {
+        self.push(segment);
+        self
+    }
+
+    /// Split the path back into its segments
+    pub fn segments(&self) -> Result<Vec<Vec<u8>>, Error> {
+        let mut segments = Vec::new();
+        let mut current = Vec::new();
+        let mut bytes = self.0.iter();
+        while let Some(b) = bytes.next() {
+            if *b != 0 {
+                current.push(*b);
+                continue;
+            }
+
+            match bytes.next() {
+                Some(0xff) => current.push(0),
+                Some(1) => segments.push(std::mem::take(&mut current)),
+                _ => return Err(Error::Message("Invalid KeyPath encoding".into())),
+            }
+        }
+
+        if !current.is_empty() {
+            return Err(Error::Message("Invalid KeyPath encoding".into()));
+        }
+
+        Ok(segments)
+    }
+}
+
+impl<S: AsRef<[u8]>> FromIterator<S> for KeyPath {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> KeyPath {
+        let mut path = KeyPath::new();
+        for segment in iter {
+            path.push(segment);
+        }
+        path
+    }
+}
+
+impl AsRef<[u8]> for KeyPath {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Path or OS string key, stored using the platform's encoding of the string
+///
+/// `PathBuf` and `OsString` can't implement `Key` themselves because `Key` requires
+/// `AsRef<[u8]>`. On Unix the raw bytes of the path are stored, so paths that aren't valid UTF-8
+/// round-trip exactly. On other platforms only paths that are valid UTF-8 can be decoded.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OsKey(pub OsString);
+
+impl OsKey {
+    /// Borrow the key as a `Path`
+    pub fn as_path(&self) -> &Path {
+        Path::new(&self.0)
+    }
+
+    /// Convert the key into a `PathBuf`
+    pub fn into_path_buf(self) -> PathBuf {
+        self.0.into()
+    }
+
+    /// Convert the key into an `OsString`
+    pub fn into_os_string(self) -> OsString {
+        self.0
+    }
+}
+
+impl From<OsString> for OsKey {
+    fn from(s: OsString) -> OsKey {
+        OsKey(s)
+    }
+}
+
+impl From<&OsStr> for OsKey {
+    fn from(s: &OsStr) -> OsKey {
+        OsKey(s.to_os_string())
+    }
+}
+
+impl From<PathBuf> for OsKey {
+    fn from(p: PathBuf) -> OsKey {
+        OsKey(p.into_os_string())
+    }
+}
+
+impl From<&Path> for OsKey {
+    fn from(p: &Path) -> OsKey {
+        OsKey(p.as_os_str().to_os_string())
+    }
+}
+
+impl AsRef<[u8]> for OsKey {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_encoded_bytes()
+    }
+}
+
+/// Unique, time-sortable key: a 48-bit millisecond timestamp, a 32-bit sequence number and 48
+/// random bits
+///
+/// The sequence number comes from a counter shared by every `Ulid` generated in the process, so
+/// keys generated one after another within the same millisecond are still strictly ordered.
+#[cfg(feature = "ulid")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Ulid([u8; 16]);
+
+#[cfg(feature = "ulid")]
+impl Ulid {
+    /// Generate a new `Ulid` using the current time
+    pub fn new() -> Result<Ulid, Error> {
+        static SEQUENCE: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
+        let ts = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+        let seq = SEQUENCE.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let mut buf = [0u8; 16];
+        buf[..6].copy_from_slice(&(ts.as_millis() as u64).to_be_bytes()[2..]);
+        buf[6..10].copy_from_slice(&seq.to_be_bytes());
+        buf[10..].copy_from_slice(&crate::bucket::random_u64().to_be_bytes()[2..]);
+        Ok(Ulid(buf))
+    }
+
+    /// Milliseconds from the Unix epoch when the `Ulid` was generated
+    pub fn timestamp_ms(&self) -> u64 {
+        let mut ts = [0u8; 8];
+        ts[2..].copy_from_slice(&self.0[..6]);
+        u64::from_be_bytes(ts)
+    }
+}
+
+#[cfg(feature = "ulid")]
+impl From<Ulid> for u128 {
+    fn from(u: Ulid) -> u128 {
+        u128::from_be_bytes(u.0)
+    }
+}
+
+#[cfg(feature = "ulid")]
+impl From<u128> for Ulid {
+    fn from(u: u128) -> Ulid {
+        Ulid(u.to_be_bytes())
+    }
+}
+
+#[cfg(feature = "ulid")]
+impl AsRef<[u8]> for Ulid {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
 }
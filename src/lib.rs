@@ -75,22 +75,52 @@
 //! ```
 
 mod bucket;
+#[cfg(feature = "cache")]
+mod cached;
+mod chunked;
 mod codec;
 mod config;
+mod counted;
+mod counters;
 mod error;
 mod key;
+mod mapped;
+mod migrator;
+mod namespace;
+mod ops;
+mod prefixed;
+mod snapshot;
 mod store;
 mod transaction;
 mod value;
+mod versioned;
+mod watch;
 
-pub use bucket::{Batch, Bucket, Event, Item, Iter, Watch};
+pub use bucket::{Batch, Bucket, DecodedEvent, Diff, Event, Item, Iter, Op, OwnedBucket, Watch};
+#[cfg(feature = "cache")]
+pub use cached::CachedBucket;
+pub use chunked::{Chunked, CHUNK_SIZE};
 pub use codec::*;
-pub use config::Config;
+pub use config::{Config, CONFIG_VERSION};
+pub use counted::CountedBucket;
+pub use counters::Counters;
 pub use error::Error;
-pub use key::{Integer, Key};
-pub use store::Store;
-pub use transaction::{Transaction, TransactionError};
-pub use value::{Raw, Value};
+#[cfg(feature = "ulid")]
+pub use key::Ulid;
+pub use key::{Bool, Char, Integer, Key, KeyPath, OsKey};
+pub use mapped::{MappedBucket, MappedItem, MappedIter};
+pub use migrator::{MigrationFn, Migrator};
+pub use namespace::Namespace;
+pub use ops::OpCounts;
+pub use prefixed::PrefixedBucket;
+pub use snapshot::Snapshot;
+pub use store::{MergeFn, MergePolicy, MergeReport, Store, StoreInfo};
+pub use transaction::{Transaction, TransactionError, Write};
+pub use value::{
+    raw_static, ByteEnum, Checked, DecodeFn, Deferred, Enum8, Raw, Schema, Tagged, Value, Versioned,
+};
+pub use versioned::VersionedBucket;
+pub use watch::{BufferedWatch, ChangeSummary, CoalescedWatch, Overflow};
 
 /// Abort a transaction
 pub fn abort<E>(x: E) -> TransactionError<E> {
@@ -0,0 +1,87 @@
+use std::marker::PhantomData;
+
+use crate::{Bucket, Error, Key, Raw, Value};
+
+/// A read-only view of a bucket that applies a function to each decoded value, see `Bucket::map`
+pub struct MappedBucket<'a, K: Key<'a>, V: Value, U, F: Fn(V) -> U> {
+    bucket: Bucket<'a, K, V>,
+    f: F,
+    phantom: PhantomData<U>,
+}
+
+/// Iterator over keys and mapped values
+pub struct MappedIter<'b, K, V, F>(sled::Iter, &'b F, PhantomData<K>, PhantomData<V>);
+
+/// Key paired with a mapped value
+pub struct MappedItem<K, U>(Raw, U, PhantomData<K>);
+
+impl<'a, K: Key<'a>, U> MappedItem<K, U> {
+    /// Get the key
+    pub fn key<T>(&'a self) -> Result<T, Error>
+    where
+        K: Into<T>,
+    {
+        let k = K::from_raw_key(&self.0)?;
+        Ok(k.into())
+    }
+
+    /// Get the mapped value
+    pub fn value(&self) -> &U {
+        &self.1
+    }
+
+    /// Convert into the mapped value
+    pub fn into_value(self) -> U {
+        self.1
+    }
+}
+
+impl<'a, K: Key<'a>, V: Value, U, F: Fn(V) -> U> MappedBucket<'a, K, V, U, F> {
+    pub(crate) fn new(bucket: Bucket<'a, K, V>, f: F) -> Self {
+        MappedBucket {
+            bucket,
+            f,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns true if the bucket contains the given key
+    pub fn contains(&self, key: &K) -> Result<bool, Error> {
+        self.bucket.contains(key)
+    }
+
+    /// Get the mapped value associated with the specified key
+    pub fn get(&self, key: &K) -> Result<Option<U>, Error> {
+        Ok(self.bucket.get(key)?.map(&self.f))
+    }
+
+    /// Get an iterator over keys and mapped values
+    pub fn iter(&self) -> MappedIter<'_, K, V, F> {
+        MappedIter(self.bucket.0.iter(), &self.f, PhantomData, PhantomData)
+    }
+}
+
+impl<'a, 'b, K: Key<'a>, V: Value, U, F: Fn(V) -> U> MappedIter<'b, K, V, F> {
+    fn map_item(&self, kv: sled::Result<(Raw, Raw)>) -> Result<MappedItem<K, U>, Error> {
+        let (k, v) = kv?;
+        Ok(MappedItem(k, (self.1)(V::from_raw_value(v)?), PhantomData))
+    }
+}
+
+impl<'a, 'b, K: Key<'a>, V: Value, U, F: Fn(V) -> U> Iterator for MappedIter<'b, K, V, F> {
+    type Item = Result<MappedItem<K, U>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let kv = self.0.next()?;
+        Some(self.map_item(kv))
+    }
+}
+
+impl<'a, 'b, K: Key<'a>, V: Value, U, F: Fn(V) -> U> DoubleEndedIterator
+    for MappedIter<'b, K, V, F>
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let kv = self.0.next_back()?;
+        Some(self.map_item(kv))
+    }
+}
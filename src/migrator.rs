@@ -0,0 +1,77 @@
+use std::sync::Mutex;
+
+use crate::{Error, Store};
+
+// Starts with `RESERVED_PREFIX` so it is hidden from `Store::buckets` and exports
+pub(crate) const MIGRATIONS_TREE: &str = "__kv__migrations";
+const VERSION_KEY: &str = "version";
+
+fn decode_version(version: Option<u64>) -> Result<u32, Error> {
+    u32::try_from(version.unwrap_or(0)).map_err(|_| Error::Message("Invalid schema version".into()))
+}
+
+/// A migration step, see `Migrator::step`
+pub type MigrationFn = Box<dyn Fn(&Store) -> Result<(), Error>>;
+
+/// Runs ordered schema migrations, recording the current schema version in the store
+///
+/// Step `n` (starting at 1) upgrades the schema from version `n - 1` to version `n`. The version
+/// is stored and flushed after each step completes, so after a crash `run` resumes from the first
+/// step that hasn't finished. A step that was interrupted is run again from the start, so steps
+/// should be idempotent.
+#[derive(Default)]
+pub struct Migrator {
+    steps: Vec<MigrationFn>,
+}
+
+impl Migrator {
+    /// Create a new migrator with no steps
+    pub fn new() -> Migrator {
+        Migrator::default()
+    }
+
+    /// Add the next migration step
+    pub fn step(mut self, f: impl Fn(&Store) -> Result<(), Error> + 'static) -> Migrator {
+        self.steps.push(Box::new(f));
+        self
+    }
+
+    /// The schema version after every step has run
+    pub fn latest_version(&self) -> u32 {
+        self.steps.len() as u32
+    }
+
+    /// Get the schema version stored in `store`, 0 if no migrations have run
+    pub fn version(store: &Store) -> Result<u32, Error> {
+        let meta = store.bucket::<&str, u64>(Some(MIGRATIONS_TREE))?;
+        decode_version(meta.get(&VERSION_KEY)?)
+    }
+
+    /// Run the steps that haven't been applied to `store` yet, returning the new schema version
+    ///
+    /// Fails without running anything if the stored version is newer than `latest_version`.
+    /// Migrations are run by at most one thread at a time.
+    pub fn run(&self, store: &Store) -> Result<u32, Error> {
+        static RUNNING: Mutex<()> = Mutex::new(());
+        let _guard = RUNNING.lock()?;
+
+        let meta = store.bucket::<&str, u64>(Some(MIGRATIONS_TREE))?;
+        let mut version = decode_version(meta.get(&VERSION_KEY)?)?;
+        if version > self.latest_version() {
+            return Err(Error::Message(format!(
+                "Schema version {} is newer than the latest known version {}",
+                version,
+                self.latest_version()
+            )));
+        }
+
+        for step in &self.steps[version as usize..] {
+            step(store)?;
+            version += 1;
+            meta.set(&VERSION_KEY, &u64::from(version))?;
+            meta.flush()?;
+        }
+
+        Ok(version)
+    }
+}
@@ -0,0 +1,53 @@
+use crate::{Bucket, Error, Key, Store, Value};
+
+/// A logical database within a `Store`, see `Store::namespace`
+///
+/// Bucket names are prefixed with the namespace name followed by `/`, so `bucket(Some("users"))`
+/// in the `app1` namespace opens the `app1/users` bucket. Buckets opened directly on the `Store`
+/// using the prefixed name refer to the same data.
+#[derive(Clone, Debug)]
+pub struct Namespace {
+    store: Store,
+    prefix: String,
+}
+
+impl Namespace {
+    pub(crate) fn new(store: Store, name: &str) -> Namespace {
+        Namespace {
+            store,
+            prefix: format!("{}/", name),
+        }
+    }
+
+    fn bucket_name(&self, name: Option<&str>) -> String {
+        format!("{}{}", self.prefix, name.unwrap_or("__sled__default"))
+    }
+
+    /// Get the name of the namespace
+    pub fn name(&self) -> &str {
+        &self.prefix[..self.prefix.len() - 1]
+    }
+
+    /// Get a list of bucket names in the namespace, without the namespace prefix
+    pub fn buckets(&self) -> Vec<String> {
+        self.store
+            .buckets()
+            .into_iter()
+            .filter_map(|x| x.strip_prefix(&self.prefix).map(String::from))
+            .collect()
+    }
+
+    /// Open a new bucket in the namespace
+    pub fn bucket<'a, K: Key<'a>, V: Value>(
+        &self,
+        name: Option<&str>,
+    ) -> Result<Bucket<'a, K, V>, Error> {
+        self.store.bucket(Some(&self.bucket_name(name)))
+    }
+
+    /// Remove a bucket from the namespace
+    pub fn drop_bucket<S: AsRef<str>>(&self, name: S) -> Result<(), Error> {
+        self.store
+            .drop_bucket(self.bucket_name(Some(name.as_ref())))
+    }
+}
@@ -0,0 +1,77 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Cumulative operation counts since a `Store` was opened, see `Store::op_counts`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpCounts {
+    /// Number of keys looked up outside of a transaction using `Bucket::get`, `get_as`,
+    /// `contains`, `get_large` or `Counters::get`. Reads made by write helpers such as
+    /// `compare_and_compute` are not counted
+    pub gets: u64,
+    /// Number of keys written by a bucket outside of a transaction, including batches,
+    /// compare-and-swap and the other bulk helpers
+    pub sets: u64,
+    /// Number of keys removed by a bucket outside of a transaction, `Bucket::clear` counts as a
+    /// single remove
+    pub removes: u64,
+    /// Number of transactions started on a bucket. Writes made by a transaction, or by a helper
+    /// such as `Bucket::rename_key` that uses one, are not counted as sets or removes
+    pub transactions: u64,
+    /// Number of times a transaction was retried after a conflict
+    pub conflicts: u64,
+}
+
+/// Shared by a store and every bucket opened from it
+#[derive(Debug, Default)]
+pub(crate) struct OpCounters {
+    gets: AtomicU64,
+    sets: AtomicU64,
+    removes: AtomicU64,
+    transactions: AtomicU64,
+    conflicts: AtomicU64,
+}
+
+impl OpCounters {
+    pub(crate) fn get(&self) {
+        self.gets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set(&self) {
+        self.sets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn remove(&self) {
+        self.removes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a single write that either sets or removes a key
+    pub(crate) fn write(&self, removed: bool) {
+        if removed {
+            self.remove()
+        } else {
+            self.set()
+        }
+    }
+
+    pub(crate) fn batch(&self, sets: u64, removes: u64) {
+        self.sets.fetch_add(sets, Ordering::Relaxed);
+        self.removes.fetch_add(removes, Ordering::Relaxed);
+    }
+
+    pub(crate) fn transaction(&self) {
+        self.transactions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn conflict(&self) {
+        self.conflicts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn counts(&self) -> OpCounts {
+        OpCounts {
+            gets: self.gets.load(Ordering::Relaxed),
+            sets: self.sets.load(Ordering::Relaxed),
+            removes: self.removes.load(Ordering::Relaxed),
+            transactions: self.transactions.load(Ordering::Relaxed),
+            conflicts: self.conflicts.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+
+use crate::{Bucket, Error, Item, Key, Raw, Value};
+
+// Starts with `RESERVED_PREFIX` so it is hidden from `Store::buckets` and exports
+pub(crate) const PREFIXES_TREE: &str = "__kv__prefixes";
+
+const BEFORE: u8 = 0;
+const PREFIXED: u8 = 1;
+const AFTER: u8 = 2;
+
+fn decode(prefix: &[u8], key: &[u8]) -> Result<Raw, Error> {
+    match key.split_first() {
+        Some((&PREFIXED, rest)) => {
+            let mut k = Vec::with_capacity(prefix.len() + rest.len());
+            k.extend_from_slice(prefix);
+            k.extend_from_slice(rest);
+            Ok(k.into())
+        }
+        Some((&BEFORE, rest)) | Some((&AFTER, rest)) => Ok(rest.into()),
+        _ => Err(Error::Message("Invalid prefixed key".into())),
+    }
+}
+
+/// A bucket that stores keys relative to a common prefix, see `Store::prefixed_bucket`
+///
+/// Keys that start with the prefix are stored as a tag byte followed by the rest of the key, so
+/// the prefix itself is only stored once, in the store's metadata. Other keys are stored in full
+/// after a tag byte that keeps them ordered before or after the prefixed keys. The mapping
+/// preserves key order, so iteration, ranges and prefix scans work on the full keys.
+#[derive(Clone)]
+pub struct PrefixedBucket<'a, K: Key<'a>, V: Value> {
+    bucket: Bucket<'a, Raw, V>,
+    prefix: Raw,
+    phantom: PhantomData<K>,
+}
+
+impl<'a, K: Key<'a>, V: Value> PrefixedBucket<'a, K, V> {
+    pub(crate) fn new(
+        bucket: Bucket<'a, Raw, V>,
+        meta: sled::Tree,
+        prefix: &[u8],
+    ) -> Result<Self, Error> {
+        let name = bucket.0.name();
+        let prefix = Raw::from(prefix);
+        let mismatch = || {
+            Error::Message(format!(
+                "Bucket {} was created with a different key prefix",
+                String::from_utf8_lossy(&name)
+            ))
+        };
+
+        match meta.get(&name)? {
+            Some(p) if p == prefix => (),
+            Some(_) => return Err(mismatch()),
+            // Existing keys weren't stored relative to the prefix
+            None if !bucket.is_empty() => return Err(mismatch()),
+            None => {
+                if let Err(e) = meta.compare_and_swap(&name, None as Option<Raw>, Some(&prefix))? {
+                    if e.current.as_ref() != Some(&prefix) {
+                        return Err(mismatch());
+                    }
+                }
+            }
+        }
+
+        Ok(PrefixedBucket {
+            bucket,
+            prefix,
+            phantom: PhantomData,
+        })
+    }
+
+    fn encode(&self, key: &[u8]) -> Raw {
+        let (tag, rest) = match key.strip_prefix(self.prefix.as_ref()) {
+            Some(rest) => (PREFIXED, rest),
+            None if key < self.prefix.as_ref() => (BEFORE, key),
+            None => (AFTER, key),
+        };
+        let mut k = Vec::with_capacity(rest.len() + 1);
+        k.push(tag);
+        k.extend_from_slice(rest);
+        k.into()
+    }
+
+    fn items(
+        &self,
+        iter: sled::Iter,
+    ) -> impl DoubleEndedIterator<Item = Result<Item<K, V>, Error>> {
+        let prefix = self.prefix.clone();
+        iter.map(move |kv| {
+            let (k, v) = kv?;
+            Ok(Item(decode(&prefix, &k)?, v, PhantomData, PhantomData))
+        })
+    }
+
+    /// Get the common key prefix
+    pub fn prefix(&self) -> &[u8] {
+        &self.prefix
+    }
+
+    /// Get the underlying bucket, which contains the stored form of each key
+    pub fn bucket(&self) -> &Bucket<'a, Raw, V> {
+        &self.bucket
+    }
+
+    /// Returns true if the bucket contains the given key
+    pub fn contains(&self, key: &K) -> Result<bool, Error> {
+        self.bucket.contains(&self.encode(&key.to_raw_key()?))
+    }
+
+    /// Get the value associated with the specified key
+    pub fn get(&self, key: &K) -> Result<Option<V>, Error> {
+        self.bucket.get(&self.encode(&key.to_raw_key()?))
+    }
+
+    /// Set the value associated with the specified key to the provided value
+    pub fn set(&self, key: &K, value: &V) -> Result<Option<V>, Error> {
+        self.bucket.set(&self.encode(&key.to_raw_key()?), value)
+    }
+
+    /// Remove the value associated with the specified key from the database
+    pub fn remove(&self, key: &K) -> Result<Option<V>, Error> {
+        self.bucket.remove(&self.encode(&key.to_raw_key()?))
+    }
+
+    /// Get the number of items in the bucket
+    pub fn len(&self) -> usize {
+        self.bucket.len()
+    }
+
+    /// Returns true when the bucket is empty
+    pub fn is_empty(&self) -> bool {
+        self.bucket.is_empty()
+    }
+
+    /// Iterate over keys/values in key order
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = Result<Item<K, V>, Error>> {
+        self.items(self.bucket.0.iter())
+    }
+
+    /// Iterate over the keys/values in the specified range
+    pub fn range<R: RangeBounds<K>>(
+        &self,
+        range: R,
+    ) -> Result<impl DoubleEndedIterator<Item = Result<Item<K, V>, Error>>, Error> {
+        let bound = |b: Bound<&K>| -> Result<Bound<Raw>, Error> {
+            Ok(match b {
+                Bound::Included(k) => Bound::Included(self.encode(&k.to_raw_key()?)),
+                Bound::Excluded(k) => Bound::Excluded(self.encode(&k.to_raw_key()?)),
+                Bound::Unbounded => Bound::Unbounded,
+            })
+        };
+        let bounds = (bound(range.start_bound())?, bound(range.end_bound())?);
+        Ok(self.items(self.bucket.0.range(bounds)))
+    }
+
+    /// Iterate over the keys/values that start with `prefix`
+    pub fn iter_prefix(
+        &self,
+        prefix: &K,
+    ) -> Result<impl DoubleEndedIterator<Item = Result<Item<K, V>, Error>>, Error> {
+        let start = prefix.to_raw_key()?;
+
+        // Scan from `start` up to the first key that doesn't start with it, found by
+        // incrementing the last byte that isn't 0xff
+        let mut end = start.to_vec();
+        while let Some(last) = end.pop() {
+            if last < 0xff {
+                end.push(last + 1);
+                break;
+            }
+        }
+        let end = if end.is_empty() {
+            Bound::Unbounded
+        } else {
+            Bound::Excluded(self.encode(&end))
+        };
+
+        let bounds = (Bound::Included(self.encode(&start)), end);
+        Ok(self.items(self.bucket.0.range(bounds)))
+    }
+}
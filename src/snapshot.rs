@@ -0,0 +1,125 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::bucket::transaction_result;
+use crate::{Error, Item, Key, Raw, TransactionError, Value};
+
+// How often keys changed while the snapshot is taken are collected, sled blocks writers to the
+// bucket once a watch has 1024 undelivered events
+const DRAIN_INTERVAL: Duration = Duration::from_millis(1);
+
+struct Changes {
+    subscriber: sled::Subscriber,
+    keys: BTreeSet<Raw>,
+}
+
+impl Changes {
+    fn drain(&mut self) {
+        while let Ok(event) = self.subscriber.next_timeout(Duration::from_secs(0)) {
+            match event {
+                sled::Event::Insert { key, .. } | sled::Event::Remove { key } => {
+                    self.keys.insert(key);
+                }
+            }
+        }
+    }
+}
+
+/// A fixed, read-only view of a bucket
+///
+/// sled has no native snapshots, so a `Snapshot` is an in-memory copy of every key and value in
+/// the bucket at a single point in time. The keys are listed first while a watch collects the
+/// keys changed in the meantime, then every value is read inside a transaction. sled runs
+/// transactions while holding a lock that excludes all other writes to the store, so the values
+/// are read between two writes and a write is either fully included or not at all. This means:
+///
+/// - Memory usage is proportional to the size of the bucket, so snapshots are best suited to
+///   small or medium sized buckets
+/// - Every key is read twice and no other writes to the store can be made while the values are
+///   read
+/// - Writes that complete before `Bucket::read_snapshot` is called are always visible and writes
+///   made after it returns are never visible
+#[derive(Clone)]
+pub struct Snapshot<K, V>(BTreeMap<Raw, Raw>, PhantomData<K>, PhantomData<V>);
+
+impl<'a, K: Key<'a>, V: Value> Snapshot<K, V> {
+    pub(crate) fn new(tree: &sled::Tree) -> Result<Snapshot<K, V>, Error> {
+        let changes = Mutex::new(Changes {
+            subscriber: tree.watch_prefix(b""),
+            keys: BTreeSet::new(),
+        });
+        let done = AtomicBool::new(false);
+
+        let result = std::thread::scope(|s| {
+            std::thread::Builder::new()
+                .name("kv-snapshot".into())
+                .spawn_scoped(s, || {
+                    while !done.load(Ordering::Relaxed) {
+                        if let Ok(mut changes) = changes.lock() {
+                            changes.drain();
+                        }
+                        std::thread::sleep(DRAIN_INTERVAL);
+                    }
+                })?;
+
+            let result = Self::read(tree, &changes);
+            done.store(true, Ordering::Relaxed);
+            result
+        })?;
+        Ok(Snapshot(result, PhantomData, PhantomData))
+    }
+
+    fn read(tree: &sled::Tree, changes: &Mutex<Changes>) -> Result<BTreeMap<Raw, Raw>, Error> {
+        // Writes that started before the watch was created have finished once a transaction runs
+        transaction_result(tree.transaction(|_| Ok::<_, TransactionError<Error>>(())))?;
+
+        let keys = tree.iter().keys().collect::<Result<BTreeSet<_>, _>>()?;
+        transaction_result(tree.transaction(|t| {
+            let mut changes = changes
+                .lock()
+                .map_err(|_| TransactionError::Abort(Error::Poison))?;
+            changes.drain();
+
+            let mut data = BTreeMap::new();
+            for k in keys.union(&changes.keys) {
+                if let Some(v) = t.get(k)? {
+                    data.insert(k.clone(), v);
+                }
+            }
+            Ok::<_, TransactionError<Error>>(data)
+        }))
+    }
+
+    /// Returns true if the snapshot contains the given key
+    pub fn contains(&self, key: &K) -> Result<bool, Error> {
+        Ok(self.0.contains_key(&key.to_raw_key()?))
+    }
+
+    /// Get the value associated with the specified key
+    pub fn get(&self, key: &K) -> Result<Option<V>, Error> {
+        match self.0.get(&key.to_raw_key()?) {
+            None => Ok(None),
+            Some(x) => Ok(Some(V::from_raw_value(x.clone())?)),
+        }
+    }
+
+    /// Get an iterator over keys/values
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = Item<K, V>> + '_ {
+        self.0
+            .iter()
+            .map(|(k, v)| Item(k.clone(), v.clone(), PhantomData, PhantomData))
+    }
+
+    /// Get the number of items
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns true when there are no items
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
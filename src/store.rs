@@ -1,12 +1,202 @@
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use crate::{Bucket, Config, Error, Key, Value};
+use sled::Transactional;
+
+use crate::bucket::transaction_result;
+use crate::counted::{recount, COUNTS_TREE};
+use crate::ops::OpCounters;
+use crate::prefixed::PREFIXES_TREE;
+use crate::{
+    Bucket, Config, CountedBucket, Counters, Error, Event, Integer, Key, Namespace, OpCounts,
+    PrefixedBucket, Raw, Transaction, TransactionError, Value, VersionedBucket,
+};
+
+/// Prefix of the names of the trees used internally for bookkeeping, such as `COUNTS_TREE`
+pub(crate) const RESERVED_PREFIX: &str = "__kv__";
+
+const STREAM_MAGIC: &[u8] = b"kv-stream\x01";
+const STREAM_BUCKET: u8 = 1;
+const STREAM_ITEM: u8 = 2;
+const STREAM_BATCH_SIZE: usize = 1024;
+const STABLE_HEADER: &str = "kv-stable 1";
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const LOG_SET: u8 = 1;
+const LOG_REMOVE: u8 = 2;
+
+fn write_chunk<W: Write>(w: &mut W, data: &[u8]) -> Result<(), Error> {
+    w.write_all(&(data.len() as u64).to_be_bytes())?;
+    w.write_all(data)?;
+    Ok(())
+}
+
+fn read_chunk<R: Read>(r: &mut R) -> Result<Vec<u8>, Error> {
+    let mut len = [0u8; 8];
+    r.read_exact(&mut len)?;
+    let len = u64::from_be_bytes(len);
+    let mut data = Vec::new();
+    r.take(len).read_to_end(&mut data)?;
+    if data.len() as u64 != len {
+        return Err(Error::Message("Invalid export stream".into()));
+    }
+    Ok(data)
+}
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut s = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = u32::from_be_bytes([0, b[0], b[1], b[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                s.push(BASE64[(n >> (18 - 6 * i)) as usize & 63] as char);
+            } else {
+                s.push('=');
+            }
+        }
+    }
+    s
+}
+
+fn base64_decode(s: &str) -> Result<Vec<u8>, Error> {
+    let invalid = || Error::Message(format!("Invalid base64: {}", s));
+    if !s.len().is_multiple_of(4) {
+        return Err(invalid());
+    }
+    let mut data = Vec::with_capacity(s.len() / 4 * 3);
+    for chunk in s.as_bytes().chunks(4) {
+        let pad = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if pad > 2 {
+            return Err(invalid());
+        }
+        let mut n = 0u32;
+        for &c in &chunk[..4 - pad] {
+            let x = BASE64.iter().position(|&b| b == c).ok_or_else(invalid)?;
+            n = (n << 6) | x as u32;
+        }
+        n <<= 6 * pad as u32;
+        data.extend_from_slice(&n.to_be_bytes()[1..4 - pad]);
+    }
+    Ok(data)
+}
+
+/// Information about a `Store`, see `Store::info`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoreInfo {
+    /// Database path
+    pub path: PathBuf,
+
+    /// Size on disk in bytes
+    pub size_on_disk: u64,
+
+    /// Number of buckets, including the default bucket
+    pub bucket_count: usize,
+
+    /// sled on-disk format version, if it could be read
+    pub format_version: Option<String>,
+}
+
+/// Closure used by `MergePolicy::Resolve`
+pub type MergeFn = Box<dyn Fn(&Raw, &Raw, &Raw) -> Raw>;
+
+/// Determines how `Store::merge_from` handles keys that exist in both stores with different
+/// values
+pub enum MergePolicy {
+    /// Keep the value already in this store
+    KeepExisting,
+
+    /// Replace the value with the one from the other store
+    Overwrite,
+
+    /// Call the closure with the key, the existing value and the incoming value, the returned
+    /// value is stored
+    Resolve(MergeFn),
+}
+
+/// Summary of a `Store::merge_from` call
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    /// Number of keys that were copied because they did not exist in this store
+    pub inserted: usize,
+
+    /// Number of keys that existed in both stores with the same value
+    pub unchanged: usize,
+
+    /// Bucket name and key of each key that existed in both stores with different values
+    pub conflicts: Vec<(Raw, Raw)>,
+}
 
 /// Store is used to read/write data to disk using `sled`
 #[derive(Clone, Debug)]
 pub struct Store {
     config: Config,
     db: sled::Db,
+    read_only: bool,
+    ops: Arc<OpCounters>,
+}
+
+static READ_ONLY_COPIES: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+fn copy_dir(src: &Path, dest: &Path) -> Result<(), Error> {
+    std::fs::create_dir_all(dest)?;
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        let path = dest.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &path)?;
+        } else {
+            std::fs::copy(entry.path(), path)?;
+        }
+    }
+    Ok(())
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut p = path.as_os_str().to_os_string();
+    p.push(suffix);
+    PathBuf::from(p)
+}
+
+fn copy_trees(src: &sled::Db, dest: &sled::Db) -> Result<(), Error> {
+    for name in src.tree_names() {
+        let from = src.open_tree(&name)?;
+        let to = dest.open_tree(&name)?;
+        let mut batch = sled::Batch::default();
+        let mut batch_len = 0;
+        for item in from.iter() {
+            let (k, v) = item?;
+            batch.insert(k, v);
+            batch_len += 1;
+            if batch_len >= STREAM_BATCH_SIZE {
+                to.apply_batch(std::mem::take(&mut batch))?;
+                batch_len = 0;
+            }
+        }
+        to.apply_batch(batch)?;
+    }
+    dest.flush()?;
+    Ok(())
+}
+
+// Open the database at `config.path`, sled can keep the lock on a database for a short time
+// after it is dropped
+fn reopen(config: &mut Config) -> Result<sled::Db, Error> {
+    let mut attempts = 0;
+    loop {
+        match config.open() {
+            Err(Error::AlreadyOpen { .. }) if attempts < 100 => {
+                attempts += 1;
+                std::thread::sleep(std::time::Duration::from_millis(50));
+            }
+            res => return res,
+        }
+    }
 }
 
 impl Store {
@@ -15,61 +205,766 @@ impl Store {
         Ok(Store {
             db: config.open()?,
             config,
+            read_only: false,
+            ops: Arc::default(),
+        })
+    }
+
+    /// Open a read-only view of the store at `config.path`, even while another process or
+    /// `Store` has it open for writing
+    ///
+    /// sled does not support opening a database without taking its exclusive lock, so the
+    /// database directory is copied to a temporary directory which is opened instead, and
+    /// removed when the store is dropped. The view contains the data as of the writer's last
+    /// flush and does not see later writes. Methods that modify the store or its buckets return
+    /// `Error::ReadOnly`.
+    pub fn open_read_only(config: Config) -> Result<Store, Error> {
+        let n = READ_ONLY_COPIES.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let copy = std::env::temp_dir().join(format!("kv-read-only-{}-{}", std::process::id(), n));
+        if copy.exists() {
+            std::fs::remove_dir_all(&copy)?;
+        }
+        copy_dir(&config.path, &copy)?;
+
+        // The copy is opened instead, `config` keeps the original path so `path` reports it
+        let mut copy_config = config.clone();
+        copy_config.path = copy;
+        copy_config.temporary = true;
+        Ok(Store {
+            db: copy_config.open()?,
+            config,
+            read_only: true,
+            ops: Arc::default(),
         })
     }
 
+    /// Returns true if the store was opened using `Store::open_read_only`
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn writable(&self) -> Result<(), Error> {
+        self.limits().writable()
+    }
+
+    fn limits(&self) -> crate::config::Limits {
+        let mut limits = self.config.limits();
+        limits.read_only = self.read_only;
+        limits.ops = self.ops.clone();
+        limits
+    }
+
+    /// Flush and close the store, move its directory to `new_path` and reopen it there
+    ///
+    /// Every other handle to the store, including buckets, must be dropped first or the store
+    /// can't be reopened. Fails without closing the store if `new_path` already exists or the
+    /// store is temporary. If moving or reopening fails the directory is moved back to its
+    /// original path and the store is reopened there.
+    pub fn rename<P: AsRef<Path>>(&mut self, new_path: P) -> Result<(), Error> {
+        self.writable()?;
+        let new_path = new_path.as_ref().to_path_buf();
+        if new_path.exists() {
+            return Err(Error::Message(format!(
+                "{} already exists",
+                new_path.display()
+            )));
+        }
+        if self.config.temporary {
+            return Err(Error::Message("Can't rename a temporary store".into()));
+        }
+        self.db.flush()?;
+
+        let placeholder = sled::Config::new().temporary(true).open()?;
+        drop(std::mem::replace(&mut self.db, placeholder));
+
+        let old_path = self.config.path.clone();
+        if let Err(e) = std::fs::rename(&old_path, &new_path) {
+            self.db = reopen(&mut self.config)?;
+            return Err(e.into());
+        }
+
+        self.config.path = new_path.clone();
+        match reopen(&mut self.config) {
+            Ok(db) => {
+                self.db = db;
+                Ok(())
+            }
+            Err(e) => {
+                self.config.path = old_path.clone();
+                std::fs::rename(&new_path, &old_path)?;
+                self.db = reopen(&mut self.config)?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Remove every item from the bucket `name`, then rewrite the database into a new
+    /// directory and reopen it, so the freed space is returned to the operating system
+    ///
+    /// sled doesn't shrink its files while the database is open, so this copies every remaining
+    /// bucket into a fresh database next to the current one, at `<path>.compact`, and replaces
+    /// the current directory with it. This costs a full copy of the remaining data and needs
+    /// enough free disk space to hold it. The `generate_id` counter carries on from where it was
+    /// and the bucket's `CountedBucket` count is reset.
+    ///
+    /// This is a `Store` method rather than a `Bucket` one because a bucket can't reopen the
+    /// database it belongs to. Every other handle to the store, including buckets, must be
+    /// dropped first, since writes made through them after the copy would be lost. A temporary
+    /// store is moved to `<path>.compact` instead of being replaced in place. If the copy fails
+    /// the original directory is left in place and the store can still be used.
+    pub fn clear_and_compact(&mut self, name: &str) -> Result<(), Error> {
+        self.writable()?;
+        let path = self.config.path.clone();
+        let compact_path = with_suffix(&path, ".compact");
+        let old_path = with_suffix(&path, ".old");
+        for p in [&compact_path, &old_path] {
+            if p.exists() {
+                return Err(Error::Message(format!("{} already exists", p.display())));
+            }
+        }
+
+        self.db.open_tree(name)?.clear()?;
+        self.db.open_tree(COUNTS_TREE)?.remove(name)?;
+        self.db.flush()?;
+
+        let mut compact_config = self.config.clone();
+        compact_config.path = compact_path.clone();
+        let compact = match compact_config.open().and_then(|compact| {
+            copy_trees(&self.db, &compact)?;
+            let next = self.db.generate_id()?;
+            while compact.generate_id()? <= next {}
+            compact.flush()?;
+            Ok(compact)
+        }) {
+            Ok(compact) => compact,
+            Err(e) => {
+                let _ = std::fs::remove_dir_all(&compact_path);
+                return Err(e);
+            }
+        };
+
+        // The old directory of a temporary store is removed when it is dropped, so the copy is
+        // used where it is
+        if self.config.temporary {
+            self.db = compact;
+            self.config.path = compact_path;
+            return Ok(());
+        }
+
+        drop(compact);
+        let placeholder = sled::Config::new().temporary(true).open()?;
+        drop(std::mem::replace(&mut self.db, placeholder));
+
+        let moved = std::fs::rename(&path, &old_path).and_then(|()| {
+            std::fs::rename(&compact_path, &path).inspect_err(|_| {
+                let _ = std::fs::rename(&old_path, &path);
+            })
+        });
+        self.db = reopen(&mut self.config)?;
+        moved?;
+        std::fs::remove_dir_all(&old_path)?;
+        Ok(())
+    }
+
     /// Get the store's path
     pub fn path(&self) -> Result<&Path, Error> {
         Ok(self.config.path.as_path())
     }
 
+    /// Returns true if existing database files were found when the store was opened, rather
+    /// than the database being newly created. State is only guaranteed to be present up to the
+    /// last flush
+    ///
+    /// This does not detect an unclean shutdown: it is true after every reopen, including after
+    /// the previous process closed the store normally, so it can't be used on its own to decide
+    /// whether to verify the data after a crash.
+    pub fn was_recovered(&self) -> bool {
+        self.db.was_recovered()
+    }
+
     /// Generate monotonic ID
     pub fn generate_id(&self) -> Result<u64, Error> {
+        self.writable()?;
         let id = self.db.generate_id()?;
         Ok(id)
     }
 
     /// Get a list of bucket names
     pub fn buckets(&self) -> Vec<String> {
-        self.db
-            .tree_names()
+        self.bucket_names()
             .into_iter()
-            .map(|x| String::from_utf8(x.to_vec()))
-            .filter_map(|x| match x {
-                Ok(x) => Some(x),
-                Err(_) => None,
-            })
+            .filter_map(|x| String::from_utf8(x.to_vec()).ok())
             .collect()
     }
 
+    /// Names of all trees except the ones used internally
+    fn bucket_names(&self) -> Vec<Raw> {
+        let mut names = self.db.tree_names();
+        names.retain(|name| !name.starts_with(RESERVED_PREFIX.as_bytes()));
+        names
+    }
+
     /// Open a new bucket
     pub fn bucket<'a, K: Key<'a>, V: Value>(
         &self,
         name: Option<&str>,
     ) -> Result<Bucket<'a, K, V>, Error> {
         let t = self.db.open_tree(name.unwrap_or("__sled__default"))?;
-        Ok(Bucket::new(t))
+        Ok(Bucket::new(t, self.limits()))
+    }
+
+    /// Get a logical database whose buckets are stored with the given name prefix, see
+    /// `Namespace`
+    pub fn namespace(&self, name: &str) -> Namespace {
+        Namespace::new(self.clone(), name)
+    }
+
+    /// Call `f` with the raw key and value of each item in the named bucket, without collecting
+    /// them into memory first. This is useful for rebuilding derived data after an import
+    pub fn for_each_item(
+        &self,
+        bucket: &str,
+        mut f: impl FnMut(Raw, Raw) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        for kv in self.db.open_tree(bucket)?.iter() {
+            let (k, v) = kv?;
+            f(k, v)?;
+        }
+        Ok(())
+    }
+
+    /// Rewrite the 8-byte `Integer` keys written by older versions of this crate in the named
+    /// bucket to the current 16-byte format, returning the number of keys converted
+    ///
+    /// Keys of any other length are left unchanged. All keys are converted in a single batch, and
+    /// nothing is changed if a converted key already exists in the bucket.
+    pub fn migrate_integer_keys(&self, bucket: &str) -> Result<usize, Error> {
+        self.writable()?;
+        let tree = self.db.open_tree(bucket)?;
+        let mut batch = sled::Batch::default();
+        let mut n = 0;
+        for kv in tree.iter() {
+            let (k, v) = kv?;
+            let old: [u8; 8] = match k.as_ref().try_into() {
+                Ok(old) => old,
+                Err(_) => continue,
+            };
+            let new = Integer::from(u64::from_be_bytes(old));
+            if tree.contains_key(new)? {
+                return Err(Error::Message(format!(
+                    "Integer key {} exists in both formats",
+                    u64::from_be_bytes(old)
+                )));
+            }
+            batch.remove(k);
+            batch.insert(new.as_ref(), v);
+            n += 1;
+        }
+        tree.apply_batch(batch)?;
+        Ok(n)
+    }
+
+    /// Flush the named bucket to disk without opening a typed `Bucket`, returning the number of
+    /// bytes flushed
+    pub fn flush_bucket(&self, name: &str) -> Result<usize, Error> {
+        Ok(self.db.open_tree(name)?.flush()?)
+    }
+
+    /// Flush every bucket concurrently, each on its own blocking task, returning the total
+    /// number of bytes flushed
+    #[cfg(feature = "tokio")]
+    pub async fn flush_all_async(&self) -> Result<usize, Error> {
+        let mut tasks = tokio::task::JoinSet::new();
+        for name in self.db.tree_names() {
+            let tree = self.db.open_tree(name)?;
+            tasks.spawn_blocking(move || tree.flush());
+        }
+
+        let mut total = 0;
+        while let Some(n) = tasks.join_next().await {
+            total += n.map_err(io::Error::from)??;
+        }
+        Ok(total)
+    }
+
+    /// Open a bucket that maintains its item count, see `CountedBucket`
+    pub fn counted_bucket<'a, K: Key<'a>, V: Value>(
+        &self,
+        name: Option<&str>,
+    ) -> Result<CountedBucket<'a, K, V>, Error> {
+        let name = name.unwrap_or("__sled__default");
+        let t = self.db.open_tree(name)?;
+        let counts = self.db.open_tree(COUNTS_TREE)?;
+        CountedBucket::new(Bucket::new(t, self.limits()), counts, name.into())
+    }
+
+    /// Open a bucket with an in-memory cache of up to `capacity` decoded values, see
+    /// `CachedBucket`
+    #[cfg(feature = "cache")]
+    pub fn cached_bucket<'a, K: Key<'a>, V: Value + Clone>(
+        &self,
+        name: Option<&str>,
+        capacity: usize,
+    ) -> Result<crate::CachedBucket<'a, K, V>, Error> {
+        crate::CachedBucket::new(self.bucket(name)?, capacity)
+    }
+
+    /// Open a set of atomic counters stored in the named bucket, see `Counters`
+    pub fn counters(&self, name: &str) -> Result<Counters, Error> {
+        Ok(Counters::new(self.db.open_tree(name)?, self.limits()))
+    }
+
+    /// Open a bucket that stores keys relative to `prefix`, see `PrefixedBucket`
+    ///
+    /// The prefix is recorded in the store's metadata when the bucket is first opened this way,
+    /// opening it again with a different prefix, or opening a non-empty bucket that wasn't
+    /// created using `prefixed_bucket`, fails.
+    pub fn prefixed_bucket<'a, K: Key<'a>, V: Value>(
+        &self,
+        name: Option<&str>,
+        prefix: &[u8],
+    ) -> Result<PrefixedBucket<'a, K, V>, Error> {
+        let meta = self.db.open_tree(PREFIXES_TREE)?;
+        PrefixedBucket::new(self.bucket(name)?, meta, prefix)
+    }
+
+    /// Open a bucket that keeps every version of each value, see `VersionedBucket`
+    pub fn versioned_bucket<'a, K: Key<'a>, V: Value>(
+        &self,
+        name: Option<&str>,
+    ) -> Result<VersionedBucket<'a, K, V>, Error> {
+        Ok(VersionedBucket::new(self.bucket(name)?))
     }
 
     /// Remove a bucket from the store
     pub fn drop_bucket<S: AsRef<str>>(&self, name: S) -> Result<(), Error> {
+        self.writable()?;
         self.db.drop_tree(name.as_ref().as_bytes())?;
+        self.db.open_tree(COUNTS_TREE)?.remove(name.as_ref())?;
+        Ok(())
+    }
+
+    // Recalculate every count maintained by `CountedBucket`, removing the counts of buckets that
+    // no longer exist
+    fn recount_all(&self) -> Result<(), Error> {
+        let counts = self.db.open_tree(COUNTS_TREE)?;
+        let names = self.db.tree_names();
+        for name in counts.iter().keys() {
+            let name = name?;
+            if names.contains(&name) {
+                recount(&counts, &name, &self.db.open_tree(&name)?)?;
+            } else {
+                counts.remove(&name)?;
+            }
+        }
         Ok(())
     }
 
+    /// Atomically exchange the contents of two buckets
+    ///
+    /// Both buckets are read into memory and then rewritten in a single transaction, so readers
+    /// will see either the old or the new contents of both buckets. Writes made to either bucket
+    /// while the swap is in progress may be lost.
+    pub fn swap_buckets(&self, a: &str, b: &str) -> Result<(), Error> {
+        self.writable()?;
+        if a == b {
+            return Ok(());
+        }
+
+        let (a_name, b_name) = (a, b);
+        let a = self.db.open_tree(a)?;
+        let b = self.db.open_tree(b)?;
+        let counts = self.db.open_tree(COUNTS_TREE)?;
+        let a_items = a.iter().collect::<Result<Vec<_>, _>>()?;
+        let b_items = b.iter().collect::<Result<Vec<_>, _>>()?;
+
+        let limits = self.limits();
+        let attempts = limits.attempts();
+        let result = (&a, &b, &counts).transaction(|(a, b, counts)| {
+            attempts.next()?;
+            // Counts move with the contents, a bucket without a count gets none
+            let a_count = counts.remove(a_name)?;
+            let b_count = counts.remove(b_name)?;
+            if let Some(n) = b_count {
+                counts.insert(a_name, n)?;
+            }
+            if let Some(n) = a_count {
+                counts.insert(b_name, n)?;
+            }
+            for (k, _) in &a_items {
+                a.remove(k)?;
+            }
+            for (k, _) in &b_items {
+                b.remove(k)?;
+            }
+            for (k, v) in &b_items {
+                a.insert(k, v)?;
+            }
+            for (k, v) in &a_items {
+                b.insert(k, v)?;
+            }
+            Ok::<_, TransactionError<Error>>(())
+        });
+
+        transaction_result(result)
+    }
+
+    /// Execute a transaction over the buckets with the given names, which are passed to `f` in
+    /// the same order as untyped `Transaction`s. This is useful when the buckets involved are
+    /// only known at runtime, the names must be distinct and there must be at least one
+    pub fn transaction<
+        A,
+        E: From<sled::Error> + From<Error>,
+        F: Fn(&[Transaction<Raw, Raw>]) -> Result<A, TransactionError<E>>,
+    >(
+        &self,
+        names: &[&str],
+        f: F,
+    ) -> Result<A, E> {
+        if names.is_empty() {
+            return Err(Error::Message("No buckets in transaction".into()).into());
+        }
+        for (i, name) in names.iter().enumerate() {
+            if names[..i].contains(name) {
+                return Err(
+                    Error::Message(format!("Duplicate bucket in transaction: {}", name)).into(),
+                );
+            }
+        }
+        let trees = names
+            .iter()
+            .map(|name| self.db.open_tree(name))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let limits = self.limits();
+        let attempts = limits.attempts();
+        let result = trees[..].transaction(|trees| {
+            attempts.next()?;
+            let t = trees.iter().map(Transaction::new).collect::<Vec<_>>();
+            f(&t)
+        });
+
+        transaction_result(result)
+    }
+
     /// Returns the size on disk in bytes
     pub fn size_on_disk(&self) -> Result<u64, Error> {
         let i = self.db.size_on_disk()?;
         Ok(i)
     }
 
+    /// Estimate the number of bytes on disk used by each bucket
+    ///
+    /// sled stores every bucket in the same files, so the size of each bucket can't be measured
+    /// directly. Instead `size_on_disk` is divided between buckets in proportion to the total size
+    /// of their keys and values, which requires reading every item once. The result is
+    /// approximate: it includes space used by sled's own metadata and by data that has not been
+    /// reclaimed yet.
+    pub fn bucket_sizes(&self) -> Result<BTreeMap<String, u64>, Error> {
+        let mut logical = Vec::new();
+        let mut total = 0u128;
+        for name in self.bucket_names() {
+            let mut n = 0u128;
+            for kv in self.db.open_tree(&name)?.iter() {
+                let (k, v) = kv?;
+                n += (k.len() + v.len()) as u128;
+            }
+            total += n;
+            logical.push((String::from_utf8_lossy(&name).into_owned(), n));
+        }
+
+        let size = self.size_on_disk()? as u128;
+        Ok(logical
+            .into_iter()
+            .map(|(name, n)| {
+                let share = (size * n).checked_div(total).unwrap_or(0);
+                (name, share as u64)
+            })
+            .collect())
+    }
+
+    /// Get the number of operations performed on the store and its buckets since it was opened
+    pub fn op_counts(&self) -> OpCounts {
+        self.ops.counts()
+    }
+
+    /// Get information about the store
+    pub fn info(&self) -> Result<StoreInfo, Error> {
+        let format_version = std::fs::File::open(self.config.path.join("conf"))
+            .ok()
+            .and_then(|f| {
+                io::BufReader::new(f)
+                    .lines()
+                    .map_while(Result::ok)
+                    .find_map(|line| line.strip_prefix("version: ").map(String::from))
+            });
+
+        Ok(StoreInfo {
+            path: self.config.path.clone(),
+            size_on_disk: self.size_on_disk()?,
+            bucket_count: self.bucket_names().len(),
+            format_version,
+        })
+    }
+
     /// Export entire database
+    #[allow(clippy::type_complexity)]
     pub fn export(&self) -> Vec<(Vec<u8>, Vec<u8>, impl Iterator<Item = Vec<Vec<u8>>>)> {
         self.db.export()
     }
 
     /// Import from database export
-    pub fn import(&self, export: Vec<(Vec<u8>, Vec<u8>, impl Iterator<Item = Vec<Vec<u8>>>)>) {
-        self.db.import(export)
+    pub fn import(
+        &self,
+        export: Vec<(Vec<u8>, Vec<u8>, impl Iterator<Item = Vec<Vec<u8>>>)>,
+    ) -> Result<(), Error> {
+        self.writable()?;
+        self.db.import(export);
+        self.recount_all()
+    }
+
+    /// Write every bucket to `w` in a streaming format that can be read back using
+    /// `Store::import_stream`, without loading the whole database into memory
+    ///
+    /// Only buckets are written, the trees used internally to track item counts, migration
+    /// versions and key prefixes are not. Use `Store::export` for a complete copy.
+    pub fn export_stream<W: Write>(&self, w: W) -> Result<(), Error> {
+        let mut w = io::BufWriter::new(w);
+        w.write_all(STREAM_MAGIC)?;
+        for name in self.bucket_names() {
+            w.write_all(&[STREAM_BUCKET])?;
+            write_chunk(&mut w, &name)?;
+            for kv in self.db.open_tree(&name)?.iter() {
+                let (k, v) = kv?;
+                w.write_all(&[STREAM_ITEM])?;
+                write_chunk(&mut w, &k)?;
+                write_chunk(&mut w, &v)?;
+            }
+        }
+        w.flush()?;
+        Ok(())
+    }
+
+    /// Import data written by `Store::export_stream`. Items are applied in batches of bounded
+    /// size, so memory usage does not depend on the size of the export
+    pub fn import_stream<R: Read>(&self, r: R) -> Result<(), Error> {
+        self.writable()?;
+        let mut r = io::BufReader::new(r);
+        let mut magic = vec![0u8; STREAM_MAGIC.len()];
+        r.read_exact(&mut magic)?;
+        if magic != STREAM_MAGIC {
+            return Err(Error::Message("Invalid export stream".into()));
+        }
+
+        let mut tree: Option<sled::Tree> = None;
+        let mut batch = sled::Batch::default();
+        let mut batch_len = 0;
+        loop {
+            let mut tag = [0u8; 1];
+            let tag = match r.read_exact(&mut tag) {
+                Ok(()) => tag[0],
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            };
+
+            match tag {
+                STREAM_BUCKET => {
+                    if let Some(t) = &tree {
+                        t.apply_batch(std::mem::take(&mut batch))?;
+                        batch_len = 0;
+                    }
+                    tree = Some(self.db.open_tree(read_chunk(&mut r)?)?);
+                }
+                STREAM_ITEM => {
+                    let t = match &tree {
+                        Some(t) => t,
+                        None => return Err(Error::Message("Invalid export stream".into())),
+                    };
+                    let k = read_chunk(&mut r)?;
+                    let v = read_chunk(&mut r)?;
+                    batch.insert(k, v);
+                    batch_len += 1;
+                    if batch_len >= STREAM_BATCH_SIZE {
+                        t.apply_batch(std::mem::take(&mut batch))?;
+                        batch_len = 0;
+                    }
+                }
+                _ => return Err(Error::Message("Invalid export stream".into())),
+            }
+        }
+
+        if let Some(t) = &tree {
+            t.apply_batch(batch)?;
+        }
+
+        self.recount_all()
+    }
+
+    /// Write every bucket to `w` in a deterministic text format that can be read back using
+    /// `Store::import_stable`
+    ///
+    /// Buckets are written in sorted order, each starting with a `[<name>]` line followed by one
+    /// `<key> <value>` line per item in key order. Names, keys and values are base64 encoded, so
+    /// exporting the same data always produces the same bytes, which makes the output suitable
+    /// for committing to version control and diffing. As with `export_stream`, the trees used
+    /// internally are not written.
+    pub fn export_stable<W: Write>(&self, w: W) -> Result<(), Error> {
+        let mut w = io::BufWriter::new(w);
+        writeln!(w, "{}", STABLE_HEADER)?;
+        let mut names = self.bucket_names();
+        names.sort();
+        for name in names {
+            writeln!(w, "[{}]", base64_encode(&name))?;
+            for kv in self.db.open_tree(&name)?.iter() {
+                let (k, v) = kv?;
+                writeln!(w, "{} {}", base64_encode(&k), base64_encode(&v))?;
+            }
+        }
+        w.flush()?;
+        Ok(())
+    }
+
+    /// Import data written by `Store::export_stable`
+    pub fn import_stable<R: Read>(&self, r: R) -> Result<(), Error> {
+        self.writable()?;
+        let invalid = || Error::Message("Invalid stable export".into());
+        let mut lines = io::BufReader::new(r).lines();
+        match lines.next().transpose()? {
+            Some(line) if line == STABLE_HEADER => (),
+            _ => return Err(invalid()),
+        }
+
+        let mut tree: Option<sled::Tree> = None;
+        let mut batch = sled::Batch::default();
+        for line in lines {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|x| x.strip_suffix(']')) {
+                if let Some(t) = &tree {
+                    t.apply_batch(std::mem::take(&mut batch))?;
+                }
+                tree = Some(self.db.open_tree(base64_decode(name)?)?);
+                continue;
+            }
+
+            let (k, v) = line.split_once(' ').ok_or_else(invalid)?;
+            if tree.is_none() {
+                return Err(invalid());
+            }
+            batch.insert(base64_decode(k)?, base64_decode(v)?);
+        }
+
+        if let Some(t) = &tree {
+            t.apply_batch(batch)?;
+        }
+
+        self.recount_all()
+    }
+
+    /// Append the writes of a committed transaction, see `Bucket::transaction_with_writes`, to
+    /// `log` as a single entry keyed by a newly generated ID. Returns the key of the new entry
+    ///
+    /// Entries are ordered by commit as long as they are recorded in the order the transactions
+    /// committed, use `Store::replay` to apply them to another store.
+    pub fn record_transaction(
+        &self,
+        log: &Bucket<Integer, Raw>,
+        writes: &[crate::Write],
+    ) -> Result<Integer, Error> {
+        let mut entry = Vec::new();
+        for w in writes {
+            match &w.event {
+                Event::Set(item) => {
+                    entry.push(LOG_SET);
+                    write_chunk(&mut entry, &w.bucket)?;
+                    write_chunk(&mut entry, &item.0)?;
+                    write_chunk(&mut entry, &item.1)?;
+                }
+                Event::Remove(key) => {
+                    entry.push(LOG_REMOVE);
+                    write_chunk(&mut entry, &w.bucket)?;
+                    write_chunk(&mut entry, key)?;
+                }
+            }
+        }
+
+        let id = Integer::from(self.generate_id()?);
+        log.set(&id, &entry.into())?;
+        Ok(id)
+    }
+
+    /// Apply every entry written to `log` by `Store::record_transaction` to this store, in
+    /// order, returning the number of entries applied
+    pub fn replay(&self, log: &Bucket<Integer, Raw>) -> Result<usize, Error> {
+        self.writable()?;
+        let mut n = 0;
+        for item in log.iter() {
+            let entry = item?.value::<Raw>()?;
+            let mut r = entry.as_ref();
+            while let Some((&tag, rest)) = r.split_first() {
+                r = rest;
+                let tree = self.db.open_tree(read_chunk(&mut r)?)?;
+                let key = read_chunk(&mut r)?;
+                match tag {
+                    LOG_SET => {
+                        tree.insert(key, read_chunk(&mut r)?)?;
+                    }
+                    LOG_REMOVE => {
+                        tree.remove(key)?;
+                    }
+                    _ => return Err(Error::Message("Invalid log entry".into())),
+                }
+            }
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    /// Copy every bucket from `other` into this store, using `policy` to resolve keys that exist
+    /// in both with different values
+    ///
+    /// The trees used internally are not merged and are not included in the report. Counts
+    /// maintained by `CountedBucket` are recalculated for the merged buckets.
+    pub fn merge_from(&self, other: &Store, policy: MergePolicy) -> Result<MergeReport, Error> {
+        self.writable()?;
+        let counts = self.db.open_tree(COUNTS_TREE)?;
+        let mut report = MergeReport::default();
+        for name in other.bucket_names() {
+            let src = other.db.open_tree(&name)?;
+            let dst = self.db.open_tree(&name)?;
+            for kv in src.iter() {
+                let (k, v) = kv?;
+                let existing = match dst.get(&k)? {
+                    None => {
+                        dst.insert(&k, v)?;
+                        report.inserted += 1;
+                        continue;
+                    }
+                    Some(existing) => existing,
+                };
+
+                if existing == v {
+                    report.unchanged += 1;
+                    continue;
+                }
+
+                match &policy {
+                    MergePolicy::KeepExisting => (),
+                    MergePolicy::Overwrite => {
+                        dst.insert(&k, v)?;
+                    }
+                    MergePolicy::Resolve(f) => {
+                        dst.insert(&k, f(&k, &existing, &v))?;
+                    }
+                }
+                report.conflicts.push((name.clone(), k));
+            }
+
+            recount(&counts, &name, &dst)?;
+        }
+        Ok(report)
     }
 }
@@ -243,6 +243,3837 @@ fn test_watch() {
     let next = next.unwrap();
 
     assert!(next.is_remove());
-    assert!(next.value().unwrap() == None);
+    assert!(next.value().unwrap().is_none());
     assert!(next.key().unwrap() == "abc");
 }
+
+#[test]
+fn test_init_once() {
+    let path = reset("init_once");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, String>(Some("meta")).unwrap();
+
+    let handles: Vec<_> = (0..8)
+        .map(|i| {
+            let bucket = bucket.clone();
+            std::thread::spawn(move || {
+                bucket
+                    .init_once(&"schema_version", &format!("{}", i))
+                    .unwrap()
+            })
+        })
+        .collect();
+
+    let winners: Vec<bool> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    assert_eq!(winners.iter().filter(|x| **x).count(), 1);
+
+    let winner = winners.iter().position(|x| *x).unwrap();
+    assert_eq!(
+        bucket.get(&"schema_version").unwrap().unwrap(),
+        format!("{}", winner)
+    );
+    assert!(!bucket.init_once(&"schema_version", &"x".into()).unwrap());
+}
+
+#[test]
+fn test_time_values() {
+    use std::time::{Duration, SystemTime};
+
+    let path = reset("time_values");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+
+    let bucket = store.bucket::<&str, Duration>(Some("durations")).unwrap();
+    let d = Duration::new(12345, 6789);
+    bucket.set(&"d", &d).unwrap();
+    assert_eq!(bucket.get(&"d").unwrap().unwrap(), d);
+    assert_eq!(d.to_raw_value().unwrap().len(), 16);
+
+    let bucket = store.bucket::<&str, SystemTime>(Some("times")).unwrap();
+    let now = SystemTime::now();
+    bucket.set(&"now", &now).unwrap();
+    assert_eq!(bucket.get(&"now").unwrap().unwrap(), now);
+
+    let before_epoch = SystemTime::UNIX_EPOCH - Duration::from_secs(1);
+    assert!(bucket.set(&"before", &before_epoch).is_err());
+
+    assert!(matches!(
+        Duration::from_raw_value(Raw::from(b"short")),
+        Err(Error::InvalidLength {
+            expected: 16,
+            found: 5
+        })
+    ));
+}
+
+#[test]
+fn test_owned_bucket() {
+    use std::sync::Arc;
+
+    struct State {
+        users: OwnedBucket<String, String>,
+    }
+
+    let path = reset("owned_bucket");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let state = Arc::new(State {
+        users: store.bucket(Some("users")).unwrap(),
+    });
+
+    let handles: Vec<_> = (0..4)
+        .map(|i| {
+            let state = state.clone();
+            std::thread::spawn(move || {
+                state
+                    .users
+                    .set(&format!("user{}", i), &format!("{}", i))
+                    .unwrap();
+            })
+        })
+        .collect();
+
+    for h in handles {
+        h.join().unwrap();
+    }
+
+    assert_eq!(state.users.len(), 4);
+    assert_eq!(state.users.get(&"user2".to_string()).unwrap().unwrap(), "2");
+}
+
+#[test]
+fn test_transaction_with_retry() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    let path = reset("transaction_with_retry");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, String>(Some("retry")).unwrap();
+
+    // Every attempt reports a conflict, as it would under constant contention
+    let calls = AtomicUsize::new(0);
+    let res: Result<(), Error> =
+        bucket.transaction_with_retry(3, Duration::from_millis(1), |txn| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            txn.set(&"key", &"value".into())?;
+            Err(TransactionError::Conflict)
+        });
+    assert!(matches!(res, Err(Error::TransactionRetryExhausted)));
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+    assert!(!bucket.contains(&"key").unwrap());
+
+    let res: Result<(), Error> =
+        bucket.transaction_with_retry(3, Duration::from_millis(1), |txn| {
+            txn.set(&"key", &"value".into())?;
+            Ok(())
+        });
+    assert!(res.is_ok());
+    assert_eq!(bucket.get(&"key").unwrap().unwrap(), "value");
+}
+
+#[test]
+fn test_transaction_with_retry_backoff() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::time::{Duration, Instant};
+
+    let path = reset("transaction_with_retry_backoff");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, String>(Some("retry")).unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let done = Arc::new(AtomicBool::new(false));
+    let handle = {
+        let bucket = bucket.clone();
+        let done = done.clone();
+        std::thread::spawn(move || {
+            let first = AtomicBool::new(true);
+            let res: Result<(), Error> =
+                bucket.transaction_with_retry(2, Duration::from_millis(500), |txn| {
+                    txn.set(&"retried", &"value".into())?;
+                    if first.swap(false, Ordering::SeqCst) {
+                        tx.send(()).unwrap();
+                        return Err(TransactionError::Conflict);
+                    }
+                    Ok(())
+                });
+            done.store(true, Ordering::SeqCst);
+            res
+        })
+    };
+
+    // Another transaction commits while the first one is backing off
+    rx.recv().unwrap();
+    let start = Instant::now();
+    bucket
+        .transaction(|txn| {
+            txn.set(&"other", &"value".into())?;
+            Ok::<_, TransactionError<Error>>(())
+        })
+        .unwrap();
+    assert!(start.elapsed() < Duration::from_millis(250));
+    assert!(!done.load(Ordering::SeqCst));
+
+    handle.join().unwrap().unwrap();
+    assert_eq!(bucket.get(&"retried").unwrap().unwrap(), "value");
+    assert_eq!(bucket.get(&"other").unwrap().unwrap(), "value");
+}
+
+#[test]
+fn test_values_prefix() {
+    let path = reset("values_prefix");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, String>(Some("values_prefix")).unwrap();
+
+    bucket.set(&"user:1:name", &"a".into()).unwrap();
+    bucket.set(&"user:1:email", &"b".into()).unwrap();
+    bucket.set(&"user:2:name", &"c".into()).unwrap();
+
+    let values: Vec<String> = bucket
+        .values_prefix(&"user:1:")
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(values, vec!["b".to_string(), "a".to_string()]);
+
+    assert_eq!(bucket.values_prefix(&"user:3:").unwrap().count(), 0);
+}
+
+#[test]
+fn test_read_snapshot() {
+    let path = reset("read_snapshot");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, String>(Some("snapshot")).unwrap();
+
+    bucket.set(&"a", &"1".into()).unwrap();
+    bucket.set(&"b", &"2".into()).unwrap();
+
+    let snapshot = bucket.read_snapshot().unwrap();
+
+    bucket.set(&"a", &"changed".into()).unwrap();
+    bucket.remove(&"b").unwrap();
+    bucket.set(&"c", &"3".into()).unwrap();
+
+    assert_eq!(snapshot.get(&"a").unwrap().unwrap(), "1");
+    assert_eq!(snapshot.get(&"b").unwrap().unwrap(), "2");
+    assert!(!snapshot.contains(&"c").unwrap());
+    assert_eq!(snapshot.len(), 2);
+
+    let keys: Vec<String> = snapshot.iter().map(|item| item.key().unwrap()).collect();
+    assert_eq!(keys, vec!["a", "b"]);
+
+    assert_eq!(bucket.get(&"a").unwrap().unwrap(), "changed");
+}
+
+#[test]
+fn test_read_snapshot_concurrent() {
+    let path = reset("read_snapshot_concurrent");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store
+        .bucket::<Integer, u64>(Some("read_snapshot_concurrent"))
+        .unwrap();
+
+    // Every batch sets all of the keys to the same value, so a snapshot that includes part of a
+    // batch sees different values
+    let write = |bucket: &Bucket<Integer, u64>, n: u64| {
+        let mut batch = Batch::new();
+        for i in 0..200u64 {
+            batch.set(&i.into(), &n).unwrap();
+        }
+        bucket.batch(batch).unwrap();
+    };
+    write(&bucket, 0);
+
+    let done = std::sync::atomic::AtomicBool::new(false);
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            let mut n = 1;
+            while !done.load(std::sync::atomic::Ordering::Relaxed) {
+                write(&bucket, n);
+                n += 1;
+            }
+        });
+
+        let snapshots = (0..50)
+            .map(|_| bucket.read_snapshot().unwrap())
+            .collect::<Vec<_>>();
+        done.store(true, std::sync::atomic::Ordering::Relaxed);
+
+        for snapshot in snapshots {
+            let values = snapshot
+                .iter()
+                .map(|item| item.value::<u64>().unwrap())
+                .collect::<std::collections::BTreeSet<_>>();
+            assert_eq!(snapshot.len(), 200);
+            assert_eq!(values.len(), 1, "{:?}", values);
+        }
+    });
+}
+
+#[test]
+fn test_for_each_item() {
+    let path = reset("for_each_item_src");
+    let cfg = Config::new(path.clone());
+    let src = Store::new(cfg).unwrap();
+    let bucket = src.bucket::<Integer, String>(Some("data")).unwrap();
+    for i in 0..50 {
+        bucket.set(&i.into(), &format!("{}", i)).unwrap();
+    }
+
+    let path = reset("for_each_item_dest");
+    let cfg = Config::new(path.clone());
+    let dest = Store::new(cfg).unwrap();
+    dest.import(src.export()).unwrap();
+
+    let mut count = 0;
+    dest.for_each_item("data", |k, v| {
+        let k: u128 = Integer::from_raw_key(&k)?.into();
+        assert_eq!(String::from_raw_value(v)?, format!("{}", k));
+        count += 1;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(count, 50);
+}
+
+#[test]
+fn test_key_path() {
+    let path = reset("key_path");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<KeyPath, String>(Some("paths")).unwrap();
+
+    let a = KeyPath::new().with("tenant").with(b"us\0er").with("field");
+    let b = KeyPath::new().with("tenant").with("us").with(b"\0erfield");
+    let c: KeyPath = ["tenant", "user:1", "name"].iter().collect();
+    bucket.set(&a, &"a".into()).unwrap();
+    bucket.set(&b, &"b".into()).unwrap();
+    bucket.set(&c, &"c".into()).unwrap();
+    bucket
+        .set(&KeyPath::new().with("other"), &"d".into())
+        .unwrap();
+
+    assert_eq!(bucket.get(&a).unwrap().unwrap(), "a");
+    assert_eq!(bucket.get(&b).unwrap().unwrap(), "b");
+
+    let prefix = KeyPath::new().with("tenant");
+    let keys: Vec<KeyPath> = bucket
+        .iter_prefix(&prefix)
+        .unwrap()
+        .map(|item| item.unwrap().key().unwrap())
+        .collect();
+    assert_eq!(keys, vec![b.clone(), a.clone(), c.clone()]);
+
+    assert_eq!(
+        a.segments().unwrap(),
+        vec![b"tenant".to_vec(), b"us\0er".to_vec(), b"field".to_vec()]
+    );
+    assert_eq!(
+        b.segments().unwrap(),
+        vec![b"tenant".to_vec(), b"us".to_vec(), b"\0erfield".to_vec()]
+    );
+
+    assert!(KeyPath::from_raw_key(&Raw::from(b"abc\0")).is_err());
+}
+
+#[test]
+fn test_swap_buckets() {
+    let path = reset("swap_buckets");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+
+    let users = store.bucket::<&str, String>(Some("users")).unwrap();
+    users.set(&"alice", &"old".into()).unwrap();
+    users.set(&"bob", &"old".into()).unwrap();
+
+    let users_new = store.bucket::<&str, String>(Some("users_new")).unwrap();
+    users_new.set(&"alice", &"new".into()).unwrap();
+    users_new.set(&"carol", &"new".into()).unwrap();
+
+    store.swap_buckets("users", "users_new").unwrap();
+    assert_eq!(store.op_counts().transactions, 1);
+
+    assert_eq!(users.get(&"alice").unwrap().unwrap(), "new");
+    assert_eq!(users.get(&"carol").unwrap().unwrap(), "new");
+    assert!(!users.contains(&"bob").unwrap());
+    assert_eq!(users.len(), 2);
+
+    assert_eq!(users_new.get(&"alice").unwrap().unwrap(), "old");
+    assert_eq!(users_new.get(&"bob").unwrap().unwrap(), "old");
+    assert!(!users_new.contains(&"carol").unwrap());
+
+    let read_only = Store::open_read_only(Config::new(path.clone())).unwrap();
+    assert!(matches!(
+        read_only.swap_buckets("users", "users_new"),
+        Err(Error::ReadOnly)
+    ));
+}
+
+#[test]
+fn test_get_or_set_default() {
+    let path = reset("get_or_set_default");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, String>(Some("defaults")).unwrap();
+
+    assert!(!bucket.contains(&"key").unwrap());
+    assert_eq!(bucket.get_or_set_default(&"key").unwrap(), "");
+    assert_eq!(bucket.get(&"key").unwrap().unwrap(), "");
+    assert_eq!(bucket.get_or_set_default(&"key").unwrap(), "");
+
+    bucket.set(&"key", &"value".into()).unwrap();
+    assert_eq!(bucket.get_or_set_default(&"key").unwrap(), "value");
+}
+
+#[test]
+fn test_required_codecs() {
+    let path = reset("required_codecs");
+
+    let cfg = Config::new(path.clone()).require_codec("xml");
+    match Store::new(cfg) {
+        Err(Error::MissingCodec { name }) => assert_eq!(name, "xml"),
+        _ => panic!("expected MissingCodec"),
+    }
+
+    let cfg = Config::new(path.clone()).require_codec("json");
+    let store = Store::new(cfg);
+    #[cfg(feature = "json-value")]
+    assert!(store.is_ok());
+    #[cfg(not(feature = "json-value"))]
+    assert!(matches!(store, Err(Error::MissingCodec { .. })));
+}
+
+#[test]
+fn test_diff() {
+    let path = reset("diff");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let a = store.bucket::<&str, String>(Some("a")).unwrap();
+    let b = store.bucket::<&str, String>(Some("b")).unwrap();
+
+    a.set(&"same", &"1".into()).unwrap();
+    b.set(&"same", &"1".into()).unwrap();
+    a.set(&"changed", &"old".into()).unwrap();
+    b.set(&"changed", &"new".into()).unwrap();
+    a.set(&"only_a", &"2".into()).unwrap();
+    b.set(&"only_b", &"3".into()).unwrap();
+
+    let diff = a.diff(&b).unwrap();
+    assert_eq!(diff.len(), 3);
+
+    match &diff[0] {
+        Diff::Changed {
+            self_value,
+            other_value,
+            ..
+        } => {
+            assert_eq!(self_value, "old");
+            assert_eq!(other_value, "new");
+        }
+        _ => panic!("expected Changed"),
+    }
+    assert_eq!(diff[0].key().unwrap(), "changed");
+
+    match &diff[1] {
+        Diff::OnlyInSelf(item) => assert_eq!(item.value::<String>().unwrap(), "2"),
+        _ => panic!("expected OnlyInSelf"),
+    }
+    assert_eq!(diff[1].key().unwrap(), "only_a");
+
+    match &diff[2] {
+        Diff::OnlyInOther(item) => assert_eq!(item.value::<String>().unwrap(), "3"),
+        _ => panic!("expected OnlyInOther"),
+    }
+    assert_eq!(diff[2].key().unwrap(), "only_b");
+
+    assert!(a.diff(&a).unwrap().is_empty());
+}
+
+#[test]
+fn test_set_validated() {
+    let path = reset("set_validated");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, String>(Some("validated")).unwrap();
+
+    let non_empty = |v: &String| {
+        if v.is_empty() {
+            Err("value must not be empty".to_string())
+        } else {
+            Ok(())
+        }
+    };
+
+    match bucket.set_validated(&"key", &String::new(), non_empty) {
+        Err(Error::Validation(msg)) => assert_eq!(msg, "value must not be empty"),
+        _ => panic!("expected validation error"),
+    }
+    assert!(!bucket.contains(&"key").unwrap());
+
+    bucket
+        .set_validated(&"key", &"value".into(), non_empty)
+        .unwrap();
+    assert_eq!(bucket.get(&"key").unwrap().unwrap(), "value");
+}
+
+#[test]
+fn test_import_stream() {
+    let path = reset("import_stream_src");
+    let cfg = Config::new(path.clone());
+    let src = Store::new(cfg).unwrap();
+    let a = src.bucket::<Integer, String>(Some("a")).unwrap();
+    let b = src.bucket::<Integer, Raw>(Some("b")).unwrap();
+    for i in 0..20000u64 {
+        a.set(&i.into(), &format!("value {}", i)).unwrap();
+        if i % 2 == 0 {
+            b.set(&i.into(), &Raw::from(vec![i as u8; 64])).unwrap();
+        }
+    }
+
+    let mut stream = Vec::new();
+    src.export_stream(&mut stream).unwrap();
+
+    let path = reset("import_stream_dest");
+    let cfg = Config::new(path.clone());
+    let dest = Store::new(cfg).unwrap();
+    dest.import_stream(stream.as_slice()).unwrap();
+
+    let a2 = dest.bucket::<Integer, String>(Some("a")).unwrap();
+    let b2 = dest.bucket::<Integer, Raw>(Some("b")).unwrap();
+    assert_eq!(a2.len(), 20000);
+    assert_eq!(b2.len(), 10000);
+    assert_eq!(a2.checksum().unwrap(), a.checksum().unwrap());
+    assert_eq!(b2.checksum().unwrap(), b.checksum().unwrap());
+
+    assert!(dest.import_stream(&b"garbage"[..]).is_err());
+}
+
+#[test]
+fn test_map() {
+    let path = reset("map");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, Raw>(Some("map")).unwrap();
+
+    bucket.set(&"a", &Raw::from(&1u64.to_be_bytes())).unwrap();
+    bucket.set(&"b", &Raw::from(&22u64.to_be_bytes())).unwrap();
+
+    let mapped = bucket.map(|v: Raw| {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(&v);
+        u64::from_be_bytes(buf).to_string()
+    });
+
+    assert_eq!(mapped.get(&"a").unwrap().unwrap(), "1");
+    assert!(mapped.get(&"missing").unwrap().is_none());
+
+    let items: Vec<(String, String)> = mapped
+        .iter()
+        .map(|item| {
+            let item = item.unwrap();
+            (item.key().unwrap(), item.into_value())
+        })
+        .collect();
+    assert_eq!(
+        items,
+        vec![
+            ("a".to_string(), "1".to_string()),
+            ("b".to_string(), "22".to_string())
+        ]
+    );
+}
+
+#[test]
+fn test_already_open() {
+    let path = reset("already_open");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg.clone()).unwrap();
+
+    match Store::new(cfg) {
+        Err(Error::AlreadyOpen { path: p }) => assert_eq!(p, path::PathBuf::from(&path)),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("expected AlreadyOpen"),
+    }
+
+    drop(store);
+}
+
+#[test]
+fn test_sample() {
+    let path = reset("sample");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<Integer, String>(Some("sample")).unwrap();
+
+    assert!(bucket.sample().unwrap().is_none());
+
+    for i in 0..100 {
+        bucket.set(&i.into(), &format!("{}", i)).unwrap();
+    }
+
+    let mut seen = std::collections::BTreeSet::new();
+    for _ in 0..50 {
+        let item = bucket.sample().unwrap().unwrap();
+        let key: u128 = item.key().unwrap();
+        assert!(key < 100);
+        assert_eq!(item.value::<String>().unwrap(), format!("{}", key));
+        seen.insert(key);
+    }
+    assert!(seen.len() > 1);
+}
+
+#[cfg(feature = "ulid")]
+#[test]
+fn test_push_ulid() {
+    let path = reset("push_ulid");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<Ulid, String>(Some("log")).unwrap();
+
+    let mut ids = Vec::new();
+    for i in 0..5 {
+        ids.push(bucket.push_ulid(&format!("entry {}", i)).unwrap());
+        std::thread::sleep(std::time::Duration::from_millis(2));
+    }
+
+    let items: Vec<(Ulid, String)> = bucket
+        .iter()
+        .map(|item| {
+            let item = item.unwrap();
+            (item.key().unwrap(), item.value().unwrap())
+        })
+        .collect();
+
+    assert_eq!(items.len(), 5);
+    for (i, (id, value)) in items.iter().enumerate() {
+        assert_eq!(*id, ids[i]);
+        assert_eq!(*value, format!("entry {}", i));
+    }
+    assert!(ids[0].timestamp_ms() < ids[4].timestamp_ms());
+}
+
+#[test]
+fn test_transaction_update() {
+    let path = reset("transaction_update");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, String>(Some("update")).unwrap();
+
+    bucket.set(&"a", &"1".into()).unwrap();
+    bucket.set(&"b", &"remove me".into()).unwrap();
+
+    let (a, b, c) = bucket
+        .transaction(|txn| {
+            let a = txn.update(&"a", |v| v.map(|v| format!("{}{}", v, v)))?;
+            let b = txn.update(&"b", |_| None)?;
+            let c = txn.update(&"c", |v| match v {
+                Some(v) => Some(v),
+                None => Some("new".into()),
+            })?;
+            Ok::<_, TransactionError<Error>>((a, b, c))
+        })
+        .unwrap();
+
+    assert_eq!(a.unwrap(), "11");
+    assert!(b.is_none());
+    assert_eq!(c.unwrap(), "new");
+    assert_eq!(bucket.get(&"a").unwrap().unwrap(), "11");
+    assert!(!bucket.contains(&"b").unwrap());
+    assert_eq!(bucket.get(&"c").unwrap().unwrap(), "new");
+}
+
+#[test]
+fn test_counted_bucket() {
+    let path = reset("counted_bucket");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+
+    let plain = store.bucket::<&str, String>(Some("counted")).unwrap();
+    plain.set(&"existing", &"0".into()).unwrap();
+
+    let bucket = store
+        .counted_bucket::<&str, String>(Some("counted"))
+        .unwrap();
+    assert_eq!(bucket.len().unwrap(), 1);
+
+    bucket.set(&"a", &"1".into()).unwrap();
+    bucket.set(&"b", &"2".into()).unwrap();
+    assert_eq!(bucket.len().unwrap(), 3);
+
+    // Overwrites must not change the count
+    bucket.set(&"a", &"3".into()).unwrap();
+    assert_eq!(bucket.len().unwrap(), 3);
+
+    bucket.remove(&"b").unwrap();
+    assert_eq!(bucket.len().unwrap(), 2);
+
+    // Removing a missing key must not change the count
+    bucket.remove(&"missing").unwrap();
+    assert_eq!(bucket.len().unwrap(), 2);
+    assert_eq!(bucket.len().unwrap(), plain.len());
+
+    let reopened = store
+        .counted_bucket::<&str, String>(Some("counted"))
+        .unwrap();
+    assert_eq!(reopened.len().unwrap(), 2);
+}
+
+#[test]
+fn test_counted_bucket_store_changes() {
+    let path = reset("counted_bucket_store_changes");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+
+    let a = store.counted_bucket::<&str, String>(Some("a")).unwrap();
+    let b = store.counted_bucket::<&str, String>(Some("b")).unwrap();
+    a.set(&"x", &"1".into()).unwrap();
+    a.set(&"y", &"2".into()).unwrap();
+    b.set(&"z", &"3".into()).unwrap();
+    assert_eq!(store.op_counts().transactions, 3);
+
+    store.swap_buckets("a", "b").unwrap();
+    assert_eq!(a.len().unwrap(), 1);
+    assert_eq!(b.len().unwrap(), 2);
+
+    store.drop_bucket("a").unwrap();
+    let a = store.counted_bucket::<&str, String>(Some("a")).unwrap();
+    assert_eq!(a.len().unwrap(), 0);
+
+    let mut stream = Vec::new();
+    store.export_stream(&mut stream).unwrap();
+    b.remove(&"x").unwrap();
+    b.remove(&"y").unwrap();
+    a.set(&"w", &"4".into()).unwrap();
+    store.drop_bucket("b").unwrap();
+    let b = store.counted_bucket::<&str, String>(Some("b")).unwrap();
+    assert_eq!(b.len().unwrap(), 0);
+    store.import_stream(stream.as_slice()).unwrap();
+    assert_eq!(a.len().unwrap(), 1);
+    assert_eq!(b.len().unwrap(), 2);
+}
+
+#[test]
+fn test_raw_static() {
+    let path = reset("raw_static");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<Raw, String>(Some("raw_static")).unwrap();
+
+    const KEY: &[u8] = b"config:version";
+    const LONG_KEY: &[u8] = b"a key that is longer than the inline limit";
+    // sled 0.34 stores up to 22 bytes inline
+    assert!(KEY.len() <= 22);
+    assert!(LONG_KEY.len() > 22);
+
+    for i in 0..10 {
+        bucket.set(&raw_static(KEY), &format!("{}", i)).unwrap();
+    }
+    bucket.set(&raw_static(LONG_KEY), &"long".into()).unwrap();
+
+    assert_eq!(bucket.get(&raw_static(KEY)).unwrap().unwrap(), "9");
+    assert_eq!(bucket.get(&Raw::from(KEY)).unwrap().unwrap(), "9");
+    assert_eq!(bucket.get(&raw_static(LONG_KEY)).unwrap().unwrap(), "long");
+}
+
+#[cfg(feature = "csv")]
+#[test]
+fn test_export_csv() {
+    let path = reset("export_csv");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+
+    let bucket = store.bucket::<String, String>(Some("strings")).unwrap();
+    bucket.set(&"a".to_string(), &"1".into()).unwrap();
+    bucket.set(&"b".to_string(), &"2".into()).unwrap();
+
+    let mut out = Vec::new();
+    assert_eq!(bucket.export_csv(&mut out).unwrap(), 2);
+    assert_eq!(String::from_utf8(out).unwrap(), "key,value\na,1\nb,2\n");
+
+    #[cfg(feature = "json-value")]
+    {
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Row {
+            key: String,
+            name: String,
+            age: u32,
+        }
+
+        #[derive(serde::Serialize, serde::Deserialize)]
+        struct Person {
+            name: String,
+            age: u32,
+        }
+
+        #[derive(serde::Serialize, serde::Deserialize)]
+        struct Nested {
+            tags: Vec<String>,
+        }
+
+        let bucket = store
+            .bucket::<String, Json<Person>>(Some("people"))
+            .unwrap();
+        for (k, name, age) in [("p1", "alice", 30), ("p2", "bob", 40)] {
+            let person = Person {
+                name: name.into(),
+                age,
+            };
+            bucket.set(&k.to_string(), &Json(person)).unwrap();
+        }
+
+        let mut out = Vec::new();
+        assert_eq!(bucket.export_csv(&mut out).unwrap(), 2);
+
+        let rows: Vec<Row> = csv::Reader::from_reader(out.as_slice())
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                Row {
+                    key: "p1".into(),
+                    name: "alice".into(),
+                    age: 30
+                },
+                Row {
+                    key: "p2".into(),
+                    name: "bob".into(),
+                    age: 40
+                },
+            ]
+        );
+
+        let bucket = store
+            .bucket::<String, Json<Nested>>(Some("nested"))
+            .unwrap();
+        let nested = Nested {
+            tags: vec!["x".into()],
+        };
+        bucket.set(&"n".to_string(), &Json(nested)).unwrap();
+        assert!(matches!(bucket.export_csv(Vec::new()), Err(Error::Csv(_))));
+    }
+}
+
+#[test]
+fn test_insert_writer() {
+    use std::io::Write;
+
+    let path = reset("insert_writer");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store
+        .bucket::<&str, Vec<u8>>(Some("insert_writer"))
+        .unwrap();
+
+    bucket
+        .insert_writer(&"blob", 4096, |buf| {
+            for i in 0..1024u32 {
+                buf.write_all(&i.to_be_bytes())?;
+            }
+            Ok(())
+        })
+        .unwrap();
+
+    let value = bucket.get(&"blob").unwrap().unwrap();
+    assert_eq!(value.len(), 4096);
+    assert_eq!(&value[4..8], &1u32.to_be_bytes());
+
+    let res = bucket.insert_writer(&"failed", 16, |_| Err(Error::Message("failed".into())));
+    assert!(res.is_err());
+    assert!(!bucket.contains(&"failed").unwrap());
+}
+
+#[test]
+fn test_store_info() {
+    let path = reset("store_info");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let a = store.bucket::<&str, Raw>(Some("a")).unwrap();
+    store.bucket::<&str, Raw>(Some("b")).unwrap();
+    a.set(&"key", &Raw::from(b"value")).unwrap();
+    a.flush().unwrap();
+
+    let info = store.info().unwrap();
+    assert_eq!(info.path, path::PathBuf::from(&path));
+    assert_eq!(info.bucket_count, store.buckets().len());
+    assert_eq!(info.bucket_count, 3);
+    assert!(info.size_on_disk > 0);
+    assert_eq!(info.format_version.as_deref(), Some("0.34"));
+}
+
+#[test]
+fn test_watch_key() {
+    let path = reset("watch_key");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, Raw>(Some("watch_key")).unwrap();
+    let mut watch = bucket.watch_key(&"user:1").unwrap();
+
+    bucket.set(&"user:10", &Raw::from(b"ten")).unwrap();
+    bucket.set(&"user:1", &Raw::from(b"one")).unwrap();
+    bucket.remove(&"user:10").unwrap();
+    bucket.remove(&"user:1").unwrap();
+
+    let next = watch.next().unwrap().unwrap();
+    assert!(next.is_set());
+    assert_eq!(next.key().unwrap(), "user:1");
+    assert!(next.value().unwrap().unwrap() == b"one");
+
+    let next = watch.next().unwrap().unwrap();
+    assert!(next.is_remove());
+    assert_eq!(next.key().unwrap(), "user:1");
+}
+
+#[test]
+fn test_set_versioned() {
+    let path = reset("set_versioned");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store
+        .bucket::<&str, Versioned<String>>(Some("versioned"))
+        .unwrap();
+
+    assert_eq!(bucket.set_versioned(&"doc", "v1".into(), 0).unwrap(), 1);
+
+    // Both clients read version 1
+    let a = bucket.get(&"doc").unwrap().unwrap();
+    let b = bucket.get(&"doc").unwrap().unwrap();
+    assert_eq!(a.version, 1);
+    assert_eq!(a.value, "v1");
+
+    assert_eq!(
+        bucket
+            .set_versioned(&"doc", "from a".into(), a.version)
+            .unwrap(),
+        2
+    );
+
+    match bucket.set_versioned(&"doc", "from b".into(), b.version) {
+        Err(Error::VersionConflict { expected, found }) => {
+            assert_eq!(expected, 1);
+            assert_eq!(found, 2);
+        }
+        _ => panic!("expected VersionConflict"),
+    }
+
+    let current = bucket.get(&"doc").unwrap().unwrap();
+    assert_eq!(current.version, 2);
+    assert_eq!(current.value, "from a");
+}
+
+#[test]
+fn test_was_recovered() {
+    let path = reset("was_recovered");
+    let cfg = Config::new(path.clone());
+
+    let store = Store::new(cfg.clone()).unwrap();
+    assert!(!store.was_recovered());
+    let bucket = store.bucket::<&str, String>(None).unwrap();
+    bucket.set(&"key", &"value".into()).unwrap();
+    bucket.flush().unwrap();
+    drop(bucket);
+    drop(store);
+
+    // sled's background flusher keeps the database locked for a short time after the last
+    // handle is dropped
+    let mut attempts = 0;
+    let store = loop {
+        match Store::new(cfg.clone()) {
+            Err(Error::AlreadyOpen { .. }) if attempts < 100 => {
+                attempts += 1;
+                std::thread::sleep(std::time::Duration::from_millis(50));
+            }
+            res => break res.unwrap(),
+        }
+    };
+    assert!(store.was_recovered());
+    let bucket = store.bucket::<&str, String>(None).unwrap();
+    assert_eq!(bucket.get(&"key").unwrap().unwrap(), "value");
+}
+
+#[test]
+fn test_watch_prefix_coalesced() {
+    use std::time::Duration;
+
+    let path = reset("watch_prefix_coalesced");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<Integer, Raw>(Some("coalesced")).unwrap();
+    let mut watch = bucket
+        .watch_prefix_coalesced(None, Duration::from_millis(200))
+        .unwrap();
+
+    for i in 0..1000 {
+        bucket.set(&i.into(), &Raw::from(b"value")).unwrap();
+    }
+
+    let mut total = 0;
+    let mut summaries = 0;
+    let mut min = u128::MAX;
+    let mut max = 0u128;
+    while total < 1000 {
+        let summary = watch.next().unwrap();
+        total += summary.count;
+        summaries += 1;
+        min = min.min(summary.min_key().unwrap().into());
+        max = max.max(summary.max_key().unwrap().into());
+    }
+
+    assert_eq!(total, 1000);
+    assert!(summaries < 100);
+    assert_eq!(min, 0);
+    assert_eq!(max, 999);
+}
+
+#[cfg(feature = "bytes")]
+#[test]
+fn test_bytes() {
+    use bytes::Bytes;
+
+    let path = reset("bytes");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<Bytes, Bytes>(Some("bytes")).unwrap();
+
+    let key = Bytes::from_static(b"key");
+    let value = Bytes::from(vec![0u8, 1, 2, 255]);
+    bucket.set(&key, &value).unwrap();
+    assert_eq!(bucket.get(&key).unwrap().unwrap(), value);
+
+    let item = bucket.first().unwrap().unwrap();
+    let k: Bytes = item.key().unwrap();
+    let v: Bytes = item.value().unwrap();
+    assert_eq!(k, key);
+    assert_eq!(v, value);
+}
+
+#[test]
+fn test_clear_and_compact() {
+    let path = reset("clear_and_compact");
+    let cfg = Config::new(path.clone());
+    let mut store = Store::new(cfg).unwrap();
+
+    {
+        let bucket = store.counted_bucket::<Integer, Raw>(Some("big")).unwrap();
+        for i in 0..2000u64 {
+            bucket.set(&i.into(), &Raw::from(vec![7u8; 4096])).unwrap();
+        }
+        let small = store.bucket::<&str, String>(Some("small")).unwrap();
+        small.set(&"key", &"value".to_string()).unwrap();
+        store.flush_bucket("big").unwrap();
+    }
+    let full = store.size_on_disk().unwrap();
+    let id = store.generate_id().unwrap();
+
+    store.clear_and_compact("big").unwrap();
+    let compacted = store.size_on_disk().unwrap();
+    assert!(compacted < full / 4, "{} >= {} / 4", compacted, full);
+    let id2 = store.generate_id().unwrap();
+    assert!(id2 > id);
+
+    // The first reopen moves the counter forward anyway, the second does not
+    store.clear_and_compact("big").unwrap();
+    assert!(store.generate_id().unwrap() > id2);
+
+    let big = store.counted_bucket::<Integer, Raw>(Some("big")).unwrap();
+    assert_eq!(big.len().unwrap(), 0);
+    let small = store.bucket::<&str, String>(Some("small")).unwrap();
+    assert_eq!(small.get(&"key").unwrap(), Some("value".to_string()));
+    assert!(!path::Path::new(&format!("{}.compact", path)).exists());
+    assert!(!path::Path::new(&format!("{}.old", path)).exists());
+}
+
+#[test]
+fn test_clear_and_compact_temporary() {
+    let path = reset("clear_and_compact_temporary");
+    let cfg = Config::new(path.clone()).temporary(true);
+    let mut store = Store::new(cfg).unwrap();
+
+    {
+        let bucket = store.bucket::<Integer, Raw>(Some("big")).unwrap();
+        for i in 0..2000u64 {
+            bucket.set(&i.into(), &Raw::from(vec![7u8; 4096])).unwrap();
+        }
+        let small = store.bucket::<&str, String>(Some("small")).unwrap();
+        small.set(&"key", &"value".to_string()).unwrap();
+        store.flush_bucket("big").unwrap();
+    }
+    let full = store.size_on_disk().unwrap();
+
+    store.clear_and_compact("big").unwrap();
+    let compacted = store.size_on_disk().unwrap();
+    assert!(compacted < full / 4, "{} >= {} / 4", compacted, full);
+    let small = store.bucket::<&str, String>(Some("small")).unwrap();
+    assert_eq!(small.get(&"key").unwrap(), Some("value".to_string()));
+}
+
+#[test]
+fn test_item_try_value() {
+    let path = reset("item_try_value");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, Vec<u8>>(Some("try_value")).unwrap();
+
+    bucket.set(&"four", &vec![1, 2, 3, 4]).unwrap();
+    bucket.set(&"five", &vec![1, 2, 3, 4, 5]).unwrap();
+
+    let five = bucket.first().unwrap().unwrap();
+    assert!(matches!(
+        five.try_value::<[u8; 4]>(),
+        Err(Error::Conversion(_))
+    ));
+
+    let four = bucket.last().unwrap().unwrap();
+    assert_eq!(four.try_value::<[u8; 4]>().unwrap(), [1, 2, 3, 4]);
+}
+
+#[test]
+fn test_transaction_with_snapshot() {
+    let path = reset("transaction_with_snapshot");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let prices = store.bucket::<&str, String>(Some("prices")).unwrap();
+    let orders = store.bucket::<&str, String>(Some("orders")).unwrap();
+
+    prices.set(&"apple", &"3".into()).unwrap();
+    prices.set(&"pear", &"5".into()).unwrap();
+
+    orders
+        .transaction_with_snapshot(&prices, |txn, prices| {
+            for item in ["apple", "pear"] {
+                let price = prices.get(&item)?.unwrap();
+                txn.set(&item, &format!("ordered at {}", price))?;
+            }
+            Ok::<_, TransactionError<Error>>(())
+        })
+        .unwrap();
+
+    assert_eq!(orders.get(&"apple").unwrap().unwrap(), "ordered at 3");
+    assert_eq!(orders.get(&"pear").unwrap().unwrap(), "ordered at 5");
+}
+
+#[test]
+fn test_split_points() {
+    let path = reset("split_points");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<Integer, Raw>(Some("split_points")).unwrap();
+
+    assert!(bucket.split_points(4).unwrap().is_empty());
+
+    for i in 0..1000u64 {
+        bucket.set(&i.into(), &Raw::from(b"")).unwrap();
+    }
+
+    let points = bucket.split_points(4).unwrap();
+    assert_eq!(points.len(), 3);
+
+    let mut bounds = vec![Integer::from(0u64)];
+    bounds.extend(points.iter().copied());
+    bounds.push(Integer::from(1000u64));
+    for w in bounds.windows(2) {
+        let count = bucket.iter_range(&w[0], &w[1]).unwrap().count();
+        assert!((240..=260).contains(&count), "{}", count);
+    }
+
+    assert!(bucket.split_points(1).unwrap().is_empty());
+    assert_eq!(bucket.split_points(5000).unwrap().len(), 999);
+}
+
+#[test]
+fn test_range_map() {
+    let path = reset("range_map");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<Integer, String>(Some("range_map")).unwrap();
+
+    for i in 0..100u64 {
+        bucket.set(&i.into(), &format!("row{}", i)).unwrap();
+    }
+
+    let map = bucket
+        .range_map(Integer::from(10u64)..Integer::from(20u64))
+        .unwrap();
+    assert_eq!(map.len(), 10);
+    let keys: Vec<u128> = map.keys().map(|k| u128::from(*k)).collect();
+    assert_eq!(keys, (10..20).collect::<Vec<u128>>());
+    assert_eq!(map[&Integer::from(15u64)], "row15");
+
+    let map = bucket.range_map(Integer::from(95u64)..).unwrap();
+    assert_eq!(map.len(), 5);
+
+    let map = bucket.range_map(..=Integer::from(2u64)).unwrap();
+    assert_eq!(map.len(), 3);
+}
+
+#[test]
+fn test_config_version() {
+    let cfg = Config::new("./test/config_version");
+    let mut buf = Vec::new();
+    cfg.save_to(&mut buf).unwrap();
+    assert!(String::from_utf8_lossy(&buf).contains("version = 1"));
+    assert_eq!(Config::load_from(buf.as_slice()).unwrap(), cfg);
+
+    // Configs written before the version field are treated as the current version
+    let cfg = Config::load_from(&b"path = \"./test/config_version\"\n"[..]).unwrap();
+    assert_eq!(cfg.version, CONFIG_VERSION);
+
+    let toml = b"version = 99\npath = \"./test/config_version\"\n";
+    match Config::load_from(&toml[..]) {
+        Err(Error::IncompatibleConfigVersion { found, supported }) => {
+            assert_eq!(found, 99);
+            assert_eq!(supported, CONFIG_VERSION);
+        }
+        _ => panic!("expected IncompatibleConfigVersion"),
+    }
+}
+
+#[test]
+fn test_observe() {
+    let path = reset("observe");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, String>(Some("observe")).unwrap();
+
+    bucket.set(&"a", &"1".to_string()).unwrap();
+    bucket.set(&"b", &"2".to_string()).unwrap();
+
+    let writer = bucket.clone();
+    let handle = std::thread::spawn(move || {
+        writer.set(&"c", &"3".to_string()).unwrap();
+    });
+
+    let (items, watch) = bucket.observe(None).unwrap();
+    handle.join().unwrap();
+
+    let mut keys: Vec<String> = items
+        .iter()
+        .map(|item| item.key::<String>().unwrap())
+        .collect();
+    assert!(keys.len() >= 2);
+    if !keys.contains(&"c".to_string()) {
+        let event = watch.take(1).next().unwrap().unwrap();
+        assert!(event.is_set());
+        keys.push(event.key().unwrap().to_string());
+    }
+    assert!(keys.contains(&"c".to_string()));
+}
+
+#[test]
+fn test_integer_arithmetic() {
+    let i = Integer::from(10u64);
+    assert_eq!(i.checked_add(5), Some(Integer::from(15u64)));
+    assert_eq!(i.checked_sub(10), Some(Integer::from(0u64)));
+    assert_eq!(i.checked_sub(11), None);
+    assert_eq!(i.next(), Some(Integer::from(11u64)));
+    assert_eq!(i.prev(), Some(Integer::from(9u64)));
+
+    let max = Integer::from(u128::MAX);
+    assert_eq!(max.next(), None);
+    assert_eq!(max.checked_add(1), None);
+    assert_eq!(max.prev(), Some(Integer::from(u128::MAX - 1)));
+
+    let zero = Integer::from(0u128);
+    assert_eq!(zero.prev(), None);
+    assert_eq!(zero.next(), Some(Integer::from(1u128)));
+    assert_eq!(zero.checked_add(u128::MAX), Some(max));
+}
+
+#[test]
+fn test_large_value() {
+    use std::io::Read;
+
+    let path = reset("large_value");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, Raw>(Some("large_value")).unwrap();
+
+    let data: Vec<u8> = (0..10 * 1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+    let n = bucket.set_large(&"blob", data.as_slice()).unwrap();
+    assert_eq!(n, data.len() as u64);
+
+    let mut reader = bucket.get_large(&"blob").unwrap().unwrap();
+    assert_eq!(reader.len(), data.len() as u64);
+    let mut out = Vec::new();
+    reader.read_to_end(&mut out).unwrap();
+    assert!(out == data);
+
+    // Overwriting with a smaller value removes the chunks that are no longer used
+    let len = bucket.len();
+    bucket.set_large(&"blob", &b"small"[..]).unwrap();
+    assert_eq!(bucket.len(), 2);
+    assert!(len > bucket.len());
+    let mut out = Vec::new();
+    bucket
+        .get_large(&"blob")
+        .unwrap()
+        .unwrap()
+        .read_to_end(&mut out)
+        .unwrap();
+    assert_eq!(out, b"small");
+
+    assert!(bucket.get_large(&"missing").unwrap().is_none());
+}
+
+#[test]
+fn test_large_value_limits() {
+    use std::io::{self, Read};
+
+    // Overwrites the value with `small` halfway through, like a concurrent `set_large`
+    struct Racing<'a> {
+        bucket: &'a Bucket<'a, &'a str, Raw>,
+        n: usize,
+    }
+
+    impl Read for Racing<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.n == CHUNK_SIZE {
+                self.bucket.set_large(&"blob", &b"small"[..]).unwrap();
+            }
+            let n = buf.len().min(self.n);
+            buf[..n].fill(0xcc);
+            self.n -= n;
+            Ok(n)
+        }
+    }
+
+    let path = reset("large_value_limits");
+    let cfg = Config::new(path.clone()).max_value_bytes(CHUNK_SIZE / 2);
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, Raw>(Some("large_value")).unwrap();
+
+    let data = vec![0xaau8; CHUNK_SIZE];
+    assert!(matches!(
+        bucket.set_large(&"blob", data.as_slice()),
+        Err(Error::ValueTooLarge { .. })
+    ));
+    assert!(bucket.is_empty());
+
+    let path = reset("large_value_race");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, Raw>(Some("large_value")).unwrap();
+
+    let racing = Racing {
+        bucket: &bucket,
+        n: 2 * CHUNK_SIZE,
+    };
+    assert!(matches!(
+        bucket.set_large(&"blob", racing),
+        Err(Error::CompareAndSwap(_))
+    ));
+
+    // Only the manifest and the single chunk of the value that won are left
+    assert_eq!(bucket.len(), 2);
+    let mut out = Vec::new();
+    bucket
+        .get_large(&"blob")
+        .unwrap()
+        .unwrap()
+        .read_to_end(&mut out)
+        .unwrap();
+    assert_eq!(out, b"small");
+}
+
+#[test]
+fn test_large_value_overwrite() {
+    use std::io::{self, Read};
+
+    // Yields `n` bytes of `byte` and then fails, like a writer that crashes partway through
+    struct Failing {
+        byte: u8,
+        n: usize,
+    }
+
+    impl Read for Failing {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.n == 0 {
+                return Err(io::Error::other("interrupted"));
+            }
+            let n = buf.len().min(self.n);
+            buf[..n].fill(self.byte);
+            self.n -= n;
+            Ok(n)
+        }
+    }
+
+    let path = reset("large_value_overwrite");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, Raw>(Some("large_value")).unwrap();
+
+    let old = vec![0xaau8; 3 * CHUNK_SIZE];
+    bucket.set_large(&"blob", old.as_slice()).unwrap();
+    let len = bucket.len();
+
+    let mut reader = bucket.get_large(&"blob").unwrap().unwrap();
+    let mut first = vec![0u8; CHUNK_SIZE];
+    reader.read_exact(&mut first).unwrap();
+
+    // A failed overwrite leaves the old value and its chunks untouched
+    let failing = Failing {
+        byte: 0xbb,
+        n: CHUNK_SIZE + CHUNK_SIZE / 2,
+    };
+    assert!(bucket.set_large(&"blob", failing).is_err());
+    assert_eq!(bucket.len(), len);
+    let mut out = Vec::new();
+    bucket
+        .get_large(&"blob")
+        .unwrap()
+        .unwrap()
+        .read_to_end(&mut out)
+        .unwrap();
+    assert!(out == old);
+
+    let mut second = vec![0u8; CHUNK_SIZE];
+    reader.read_exact(&mut second).unwrap();
+    assert!(second.iter().all(|b| *b == 0xaa));
+
+    // Once the overwrite completes the reader fails instead of mixing in the new bytes
+    let new = vec![0xbbu8; 3 * CHUNK_SIZE];
+    bucket.set_large(&"blob", new.as_slice()).unwrap();
+    let mut rest = Vec::new();
+    assert!(reader.read_to_end(&mut rest).is_err());
+    assert!(rest.iter().all(|b| *b == 0xaa));
+
+    let mut out = Vec::new();
+    bucket
+        .get_large(&"blob")
+        .unwrap()
+        .unwrap()
+        .read_to_end(&mut out)
+        .unwrap();
+    assert!(out == new);
+    assert_eq!(bucket.len(), len);
+}
+
+#[test]
+fn test_count_range() {
+    let path = reset("count_range");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<Integer, Raw>(Some("count_range")).unwrap();
+
+    for i in 0..500u64 {
+        bucket.set(&i.into(), &Raw::from(b"x")).unwrap();
+    }
+
+    assert_eq!(
+        bucket
+            .count_range(Integer::from(100u64)..Integer::from(250u64))
+            .unwrap(),
+        150
+    );
+    assert_eq!(
+        bucket
+            .count_range(Integer::from(100u64)..=Integer::from(250u64))
+            .unwrap(),
+        151
+    );
+    assert_eq!(bucket.count_range(Integer::from(490u64)..).unwrap(), 10);
+    assert_eq!(bucket.count_range(..).unwrap(), 500);
+}
+
+#[test]
+fn test_transaction_clear_prefix() {
+    let path = reset("transaction_clear_prefix");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store
+        .bucket::<&str, String>(Some("transaction_clear_prefix"))
+        .unwrap();
+
+    for i in 0..100 {
+        bucket
+            .set(&format!("tenant1/{}", i).as_str(), &i.to_string())
+            .unwrap();
+        bucket
+            .set(&format!("tenant2/{}", i).as_str(), &i.to_string())
+            .unwrap();
+    }
+
+    let writer = bucket.clone();
+    let handle = std::thread::spawn(move || {
+        for i in 100..200 {
+            writer
+                .set(&format!("tenant2/{}", i).as_str(), &i.to_string())
+                .unwrap();
+        }
+    });
+
+    let n = bucket.transaction_clear_prefix(&"tenant1/").unwrap();
+    handle.join().unwrap();
+
+    assert_eq!(n, 100);
+    assert_eq!(bucket.iter_prefix(&"tenant1/").unwrap().count(), 0);
+    assert_eq!(bucket.iter_prefix(&"tenant2/").unwrap().count(), 200);
+}
+
+#[test]
+fn test_net_values() {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    let path = reset("net_values");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+
+    let v4 = Ipv4Addr::new(192, 168, 1, 10);
+    let v6: Ipv6Addr = "2001:db8::ff00:42:8329".parse().unwrap();
+
+    let bucket = store.bucket::<&str, Ipv4Addr>(Some("ipv4")).unwrap();
+    bucket.set(&"a", &v4).unwrap();
+    assert_eq!(bucket.get(&"a").unwrap(), Some(v4));
+
+    let bucket = store.bucket::<&str, Ipv6Addr>(Some("ipv6")).unwrap();
+    bucket.set(&"a", &v6).unwrap();
+    assert_eq!(bucket.get(&"a").unwrap(), Some(v6));
+
+    let bucket = store.bucket::<&str, IpAddr>(Some("ip")).unwrap();
+    bucket.set(&"v4", &IpAddr::V4(v4)).unwrap();
+    bucket.set(&"v6", &IpAddr::V6(v6)).unwrap();
+    assert_eq!(bucket.get(&"v4").unwrap(), Some(IpAddr::V4(v4)));
+    assert_eq!(bucket.get(&"v6").unwrap(), Some(IpAddr::V6(v6)));
+    assert_eq!(IpAddr::V4(v4).to_raw_value().unwrap().len(), 5);
+
+    let bucket = store.bucket::<&str, SocketAddr>(Some("socket")).unwrap();
+    let a: SocketAddr = "10.0.0.1:8080".parse().unwrap();
+    let b: SocketAddr = "[::1]:65535".parse().unwrap();
+    bucket.set(&"a", &a).unwrap();
+    bucket.set(&"b", &b).unwrap();
+    assert_eq!(bucket.get(&"a").unwrap(), Some(a));
+    assert_eq!(bucket.get(&"b").unwrap(), Some(b));
+
+    assert!(IpAddr::from_raw_value(Raw::from(&[5u8, 1, 2, 3, 4][..])).is_err());
+    assert!(SocketAddr::from_raw_value(Raw::from(&[4u8, 1, 2, 3, 4][..])).is_err());
+}
+
+#[test]
+fn test_transaction_with_writes() {
+    let path = reset("transaction_with_writes");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, String>(Some("writes")).unwrap();
+    bucket.set(&"old", &"x".to_string()).unwrap();
+
+    let attempts = std::cell::Cell::new(0);
+    let (n, writes) = bucket
+        .transaction_with_writes(|txn| {
+            attempts.set(attempts.get() + 1);
+            if attempts.get() == 1 {
+                txn.set(&"aborted", &"x".to_string())?;
+                return Err(TransactionError::Conflict);
+            }
+
+            txn.set(&"a", &"1".to_string())?;
+            txn.set(&"b", &"2".to_string())?;
+            txn.remove(&"old")?;
+            Ok::<_, TransactionError<Error>>(attempts.get())
+        })
+        .unwrap();
+    assert_eq!(n, 2);
+
+    assert_eq!(writes.len(), 3);
+    for w in &writes {
+        assert_eq!(w.bucket, Raw::from(b"writes"));
+        let key = w.event.key().unwrap();
+        let key = std::str::from_utf8(&key).unwrap();
+        match key {
+            "a" | "b" => {
+                let v: Raw = w.event.value().unwrap().unwrap();
+                assert_eq!(
+                    Some(String::from_utf8(v.to_vec()).unwrap()),
+                    bucket.get(&key).unwrap()
+                );
+            }
+            "old" => {
+                assert!(w.event.is_remove());
+                assert!(!bucket.contains(&"old").unwrap());
+            }
+            _ => panic!("unexpected write {}", key),
+        }
+    }
+    assert!(!bucket.contains(&"aborted").unwrap());
+}
+
+#[test]
+fn test_record_transaction() {
+    let path = reset("record_transaction");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, String>(Some("data")).unwrap();
+    let log = store.bucket::<Integer, Raw>(Some("log")).unwrap();
+
+    let (_, writes) = bucket
+        .transaction_with_writes(|txn| {
+            txn.set(&"a", &"1".to_string())?;
+            txn.set(&"b", &"2".to_string())?;
+            txn.set(&"c", &"3".to_string())?;
+            Ok::<_, TransactionError<Error>>(())
+        })
+        .unwrap();
+    store.record_transaction(&log, &writes).unwrap();
+
+    let (_, writes) = bucket
+        .transaction_with_writes(|txn| {
+            txn.remove(&"b")?;
+            txn.set(&"a", &"4".to_string())?;
+            Ok::<_, TransactionError<Error>>(())
+        })
+        .unwrap();
+    store.record_transaction(&log, &writes).unwrap();
+    assert_eq!(log.len(), 2);
+
+    let path2 = reset("record_transaction_replica");
+    let replica = Store::new(Config::new(path2)).unwrap();
+    assert_eq!(replica.replay(&log).unwrap(), 2);
+
+    let replica_bucket = replica.bucket::<&str, String>(Some("data")).unwrap();
+    assert_eq!(replica_bucket.len(), bucket.len());
+    for item in bucket.iter() {
+        let item = item.unwrap();
+        let key: &str = item.key().unwrap();
+        assert_eq!(
+            replica_bucket.get(&key).unwrap(),
+            Some(item.value::<String>().unwrap())
+        );
+    }
+    assert_eq!(replica_bucket.get(&"a").unwrap(), Some("4".to_string()));
+    assert!(!replica_bucket.contains(&"b").unwrap());
+}
+
+#[test]
+fn test_size_limits() {
+    let path = reset("size_limits");
+    let cfg = Config::new(path.clone())
+        .max_key_bytes(8)
+        .max_value_bytes(16);
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, String>(Some("size_limits")).unwrap();
+
+    bucket.set(&"ok", &"small".to_string()).unwrap();
+
+    match bucket.set(&"big", &"x".repeat(17)) {
+        Err(Error::ValueTooLarge { size, limit }) => {
+            assert_eq!(size, 17);
+            assert_eq!(limit, 16);
+        }
+        _ => panic!("expected ValueTooLarge"),
+    }
+    assert!(!bucket.contains(&"big").unwrap());
+
+    match bucket.set(&"very long key", &"small".to_string()) {
+        Err(Error::KeyTooLarge { size, limit }) => {
+            assert_eq!(size, 13);
+            assert_eq!(limit, 8);
+        }
+        _ => panic!("expected KeyTooLarge"),
+    }
+
+    let mut batch = Batch::new();
+    batch.set(&"a", &"small".to_string()).unwrap();
+    batch.set(&"b", &"x".repeat(100)).unwrap();
+    assert!(matches!(
+        bucket.batch(batch),
+        Err(Error::ValueTooLarge { .. })
+    ));
+    assert!(!bucket.contains(&"a").unwrap());
+
+    assert!(matches!(
+        bucket.compare_and_swap(&"ok", None, Some(&"x".repeat(20))),
+        Err(Error::ValueTooLarge { .. })
+    ));
+    assert_eq!(bucket.len(), 1);
+}
+
+#[test]
+fn test_upsert_missing() {
+    let path = reset("upsert_missing");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store
+        .bucket::<Integer, String>(Some("upsert_missing"))
+        .unwrap();
+
+    for i in (0..10u64).step_by(2) {
+        bucket.set(&i.into(), &"existing".to_string()).unwrap();
+    }
+
+    let items: Vec<(Integer, String)> = (0..10u64)
+        .map(|i| (i.into(), "computed".to_string()))
+        .collect();
+    assert_eq!(bucket.upsert_missing(&items).unwrap(), 5);
+    assert_eq!(bucket.len(), 10);
+    assert_eq!(
+        bucket.get(&0u64.into()).unwrap(),
+        Some("existing".to_string())
+    );
+    assert_eq!(
+        bucket.get(&1u64.into()).unwrap(),
+        Some("computed".to_string())
+    );
+
+    assert_eq!(bucket.upsert_missing(&items).unwrap(), 0);
+}
+
+#[test]
+fn test_iter_take_skip_while() {
+    let path = reset("iter_take_skip_while");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store
+        .bucket::<Integer, String>(Some("iter_take_skip_while"))
+        .unwrap();
+
+    for i in 0..20u64 {
+        bucket.set(&i.into(), &(i * 10).to_string()).unwrap();
+    }
+
+    let below = |item: &Item<Integer, String>| {
+        item.value::<String>().unwrap().parse::<u64>().unwrap() <= 100
+    };
+
+    let keys: Vec<u128> = bucket
+        .iter_take_while(below)
+        .map(|item| u128::from(item.unwrap().key::<Integer>().unwrap()))
+        .collect();
+    assert_eq!(keys, (0..=10).collect::<Vec<u128>>());
+
+    let keys: Vec<u128> = bucket
+        .iter_skip_while(below)
+        .map(|item| u128::from(item.unwrap().key::<Integer>().unwrap()))
+        .collect();
+    assert_eq!(keys, (11..20).collect::<Vec<u128>>());
+}
+
+#[test]
+fn test_merge_from() {
+    let setup = |name: &str, items: &[(&str, &str)]| {
+        let store = Store::new(Config::new(reset(name))).unwrap();
+        let bucket = store.bucket::<&str, String>(Some("data")).unwrap();
+        for (k, v) in items {
+            bucket.set(k, &v.to_string()).unwrap();
+        }
+        store
+    };
+
+    let other = setup("merge_from_other", &[("a", "2"), ("b", "same"), ("c", "3")]);
+    let items = [("a", "1"), ("b", "same")];
+
+    let store = setup("merge_from_keep", &items);
+    let report = store.merge_from(&other, MergePolicy::KeepExisting).unwrap();
+    assert_eq!(report.inserted, 1);
+    assert_eq!(report.unchanged, 1);
+    assert_eq!(
+        report.conflicts,
+        vec![(Raw::from(b"data"), Raw::from(b"a"))]
+    );
+    let bucket = store.bucket::<&str, String>(Some("data")).unwrap();
+    assert_eq!(bucket.get(&"a").unwrap(), Some("1".to_string()));
+    assert_eq!(bucket.get(&"c").unwrap(), Some("3".to_string()));
+
+    let store = setup("merge_from_overwrite", &items);
+    let report = store.merge_from(&other, MergePolicy::Overwrite).unwrap();
+    assert_eq!(report.conflicts.len(), 1);
+    let bucket = store.bucket::<&str, String>(Some("data")).unwrap();
+    assert_eq!(bucket.get(&"a").unwrap(), Some("2".to_string()));
+    assert_eq!(bucket.len(), 3);
+
+    let store = setup("merge_from_resolve", &items);
+    let policy = MergePolicy::Resolve(Box::new(|_k, a, b| {
+        let mut v = a.to_vec();
+        v.extend_from_slice(b);
+        v.into()
+    }));
+    let report = store.merge_from(&other, policy).unwrap();
+    assert_eq!(report.conflicts.len(), 1);
+    let bucket = store.bucket::<&str, String>(Some("data")).unwrap();
+    assert_eq!(bucket.get(&"a").unwrap(), Some("12".to_string()));
+    assert_eq!(bucket.get(&"b").unwrap(), Some("same".to_string()));
+}
+
+#[test]
+fn test_rename_key() {
+    let path = reset("rename_key");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, String>(Some("rename_key")).unwrap();
+
+    bucket.set(&"a", &"1".to_string()).unwrap();
+    bucket.set(&"b", &"2".to_string()).unwrap();
+
+    assert!(bucket.rename_key(&"a", &"c", false).unwrap());
+    assert!(!bucket.contains(&"a").unwrap());
+    assert_eq!(bucket.get(&"c").unwrap(), Some("1".to_string()));
+
+    assert!(!bucket.rename_key(&"c", &"b", false).unwrap());
+    assert_eq!(bucket.get(&"b").unwrap(), Some("2".to_string()));
+    assert_eq!(bucket.get(&"c").unwrap(), Some("1".to_string()));
+
+    assert!(bucket.rename_key(&"c", &"b", true).unwrap());
+    assert_eq!(bucket.get(&"b").unwrap(), Some("1".to_string()));
+    assert!(!bucket.contains(&"c").unwrap());
+
+    assert!(!bucket.rename_key(&"missing", &"d", true).unwrap());
+    assert_eq!(bucket.len(), 1);
+}
+
+#[test]
+fn test_rename_key_size_limit() {
+    let path = reset("rename_key_size_limit");
+    let cfg = Config::new(path.clone()).max_key_bytes(8);
+    let store = Store::new(cfg).unwrap();
+    let bucket = store
+        .bucket::<&str, String>(Some("rename_key_size_limit"))
+        .unwrap();
+
+    bucket.set(&"a", &"1".to_string()).unwrap();
+
+    match bucket.rename_key(&"a", &"very long key", false) {
+        Err(Error::KeyTooLarge { size, limit }) => {
+            assert_eq!(size, 13);
+            assert_eq!(limit, 8);
+        }
+        _ => panic!("expected KeyTooLarge"),
+    }
+    assert_eq!(bucket.get(&"a").unwrap(), Some("1".to_string()));
+    assert!(!bucket.contains(&"very long key").unwrap());
+}
+
+#[test]
+fn test_config_from_env() {
+    assert!(Config::from_env("KV_TEST_UNSET").is_err());
+
+    std::env::set_var("KV_TEST_ENV_PATH", "./test/config_from_env");
+    std::env::set_var("KV_TEST_ENV_USE_COMPRESSION", "true");
+    std::env::set_var("KV_TEST_ENV_CACHE_CAPACITY", "1048576");
+    std::env::set_var("KV_TEST_ENV_FLUSH_EVERY_MS", "500");
+    std::env::set_var("KV_TEST_ENV_REQUIRED_CODECS", "json, msgpack");
+
+    let cfg = Config::from_env("KV_TEST_ENV").unwrap();
+    assert_eq!(cfg.path, path::PathBuf::from("./test/config_from_env"));
+    assert!(cfg.use_compression);
+    assert!(!cfg.temporary);
+    assert_eq!(cfg.cache_capacity, Some(1048576));
+    assert_eq!(cfg.flush_every_ms, Some(500));
+    assert_eq!(cfg.segment_size, None);
+    assert_eq!(cfg.required_codecs, vec!["json", "msgpack"]);
+
+    std::env::set_var("KV_TEST_ENV_CACHE_CAPACITY", "lots");
+    assert!(Config::from_env("KV_TEST_ENV").is_err());
+}
+
+#[test]
+fn test_iter_lossy() {
+    let path = reset("iter_lossy");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<String, String>(Some("iter_lossy")).unwrap();
+    let raw = store.bucket::<Raw, Raw>(Some("iter_lossy")).unwrap();
+
+    for i in 0..5 {
+        bucket
+            .set(&format!("key{}", i), &format!("value{}", i))
+            .unwrap();
+    }
+    raw.set(&Raw::from(b"key2"), &Raw::from(&[0xff, 0xfe][..]))
+        .unwrap();
+
+    let mut bad = Vec::new();
+    let keys: Vec<String> = bucket
+        .iter_lossy(|k, _| bad.push(k))
+        .map(|item| item.key::<String>().unwrap())
+        .collect();
+    assert_eq!(keys, vec!["key0", "key1", "key3", "key4"]);
+    assert_eq!(bad, vec![Some(Raw::from(b"key2"))]);
+}
+
+#[test]
+fn test_namespace() {
+    let path = reset("namespace");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+
+    let app1 = store.namespace("app1");
+    let app2 = store.namespace("app2");
+    assert_eq!(app1.name(), "app1");
+
+    let users1 = app1.bucket::<&str, String>(Some("users")).unwrap();
+    let users2 = app2.bucket::<&str, String>(Some("users")).unwrap();
+    app2.bucket::<&str, String>(Some("orders")).unwrap();
+
+    users1.set(&"alice", &"app1".to_string()).unwrap();
+    users2.set(&"alice", &"app2".to_string()).unwrap();
+    assert_eq!(users1.get(&"alice").unwrap(), Some("app1".to_string()));
+    assert_eq!(users2.get(&"alice").unwrap(), Some("app2".to_string()));
+
+    assert_eq!(app1.buckets(), vec!["users"]);
+    let mut buckets = app2.buckets();
+    buckets.sort();
+    assert_eq!(buckets, vec!["orders", "users"]);
+    assert!(store.buckets().contains(&"app1/users".to_string()));
+
+    app2.drop_bucket("users").unwrap();
+    assert_eq!(app2.buckets(), vec!["orders"]);
+    assert_eq!(users1.get(&"alice").unwrap(), Some("app1".to_string()));
+}
+
+#[test]
+fn test_compare_and_compute() {
+    let path = reset("compare_and_compute");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store
+        .bucket::<&str, String>(Some("compare_and_compute"))
+        .unwrap();
+
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            let bucket = bucket.clone();
+            std::thread::spawn(move || {
+                for _ in 0..100 {
+                    bucket
+                        .compare_and_compute(&"counter", |v| {
+                            let n: u64 = v.map(|v| v.parse().unwrap()).unwrap_or(0);
+                            Some((n + 1).to_string())
+                        })
+                        .unwrap();
+                }
+            })
+        })
+        .collect();
+    for h in handles {
+        h.join().unwrap();
+    }
+    assert_eq!(bucket.get(&"counter").unwrap(), Some("800".to_string()));
+
+    let v = bucket.compare_and_compute(&"counter", |_| None).unwrap();
+    assert!(v.is_none());
+    assert!(!bucket.contains(&"counter").unwrap());
+}
+
+#[test]
+fn test_key_vec() {
+    let path = reset("key_vec");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<Integer, Raw>(Some("key_vec")).unwrap();
+
+    assert!(bucket.key_vec().unwrap().is_empty());
+
+    for i in (0..100u64).rev() {
+        bucket.set(&(i * 3).into(), &Raw::from(b"")).unwrap();
+    }
+
+    let keys = bucket.key_vec().unwrap();
+    assert_eq!(keys.len(), 100);
+    assert!(keys.windows(2).all(|w| w[0] < w[1]));
+    let keys: Vec<u64> = keys.into_iter().map(u64::from).collect();
+    assert_eq!(keys, (0..100).map(|i| i * 3).collect::<Vec<u64>>());
+}
+
+#[test]
+fn test_versioned_bucket() {
+    let path = reset("versioned_bucket");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store
+        .versioned_bucket::<&str, String>(Some("versioned_bucket"))
+        .unwrap();
+
+    assert!(bucket.latest(&"doc").unwrap().is_none());
+
+    assert_eq!(bucket.insert(&"doc", &"v1".to_string()).unwrap(), 1);
+    assert_eq!(bucket.insert(&"doc", &"v2".to_string()).unwrap(), 2);
+    assert_eq!(bucket.insert(&"doc", &"v3".to_string()).unwrap(), 3);
+    bucket.insert(&"docs", &"other".to_string()).unwrap();
+    bucket.insert(&"do", &"other".to_string()).unwrap();
+
+    assert_eq!(bucket.latest(&"doc").unwrap(), Some((3, "v3".to_string())));
+    assert_eq!(bucket.version(&"doc", 2).unwrap(), Some("v2".to_string()));
+    assert_eq!(bucket.version(&"doc", 4).unwrap(), None);
+    assert_eq!(
+        bucket.history(&"doc").unwrap(),
+        vec![
+            (1, "v1".to_string()),
+            (2, "v2".to_string()),
+            (3, "v3".to_string())
+        ]
+    );
+    assert_eq!(bucket.history(&"docs").unwrap().len(), 1);
+}
+
+#[test]
+fn test_transaction_read_modify_write() {
+    let path = reset("transaction_read_modify_write");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store
+        .bucket::<&str, String>(Some("transaction_read_modify_write"))
+        .unwrap();
+    bucket.set(&"balance", &"0".to_string()).unwrap();
+
+    let attempts = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let handles: Vec<_> = (0..2)
+        .map(|_| {
+            let bucket = bucket.clone();
+            let attempts = attempts.clone();
+            std::thread::spawn(move || {
+                for _ in 0..100 {
+                    bucket
+                        .transaction(|txn| {
+                            attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                            let n: u64 = txn.get(&"balance")?.unwrap().parse().unwrap();
+                            std::thread::yield_now();
+                            txn.set(&"balance", &(n + 1).to_string())?;
+                            Ok::<_, TransactionError<Error>>(())
+                        })
+                        .unwrap();
+                }
+            })
+        })
+        .collect();
+    for h in handles {
+        h.join().unwrap();
+    }
+
+    // Both threads read then write the same key, neither update may be lost. sled serializes
+    // transactions, so they never overlap and none of them are retried
+    assert_eq!(bucket.get(&"balance").unwrap(), Some("200".to_string()));
+    assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 200);
+}
+
+#[cfg(feature = "ulid")]
+#[test]
+fn test_ulid_order() {
+    let path = reset("ulid_order");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<Ulid, String>(Some("ulid_order")).unwrap();
+
+    let ids: Vec<Ulid> = (0..1000)
+        .map(|i| bucket.push_ulid(&i.to_string()).unwrap())
+        .collect();
+    assert!(ids.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(bucket.len(), 1000);
+
+    let values: Vec<String> = bucket
+        .iter()
+        .map(|item| item.unwrap().value().unwrap())
+        .collect();
+    assert_eq!(
+        values,
+        (0..1000).map(|i| i.to_string()).collect::<Vec<String>>()
+    );
+}
+
+#[cfg(feature = "cache")]
+#[test]
+fn test_cached_bucket() {
+    let path = reset("cached_bucket");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store
+        .cached_bucket::<&str, String>(Some("cached_bucket"), 16)
+        .unwrap();
+
+    bucket.set(&"a", &"1".to_string()).unwrap();
+    assert_eq!(bucket.get(&"a").unwrap(), Some("1".to_string()));
+    assert_eq!((bucket.hits(), bucket.misses()), (0, 1));
+    assert_eq!(bucket.get(&"a").unwrap(), Some("1".to_string()));
+    assert_eq!((bucket.hits(), bucket.misses()), (1, 1));
+
+    bucket.set(&"a", &"2".to_string()).unwrap();
+    assert_eq!(bucket.get(&"a").unwrap(), Some("2".to_string()));
+    assert_eq!((bucket.hits(), bucket.misses()), (1, 2));
+
+    // Writes made through another handle are detected using a watch
+    let other = store.bucket::<&str, String>(Some("cached_bucket")).unwrap();
+    other.set(&"a", &"3".to_string()).unwrap();
+    assert_eq!(bucket.get(&"a").unwrap(), Some("3".to_string()));
+    assert_eq!((bucket.hits(), bucket.misses()), (1, 3));
+
+    bucket.remove(&"a").unwrap();
+    assert_eq!(bucket.get(&"a").unwrap(), None);
+
+    assert!(store
+        .cached_bucket::<&str, String>(Some("cached_bucket"), 0)
+        .is_err());
+}
+
+#[cfg(feature = "cache")]
+#[test]
+fn test_cached_bucket_many_writes() {
+    let path = reset("cached_bucket_many_writes");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store
+        .cached_bucket::<Integer, String>(Some("cached_bucket_many_writes"), 16)
+        .unwrap();
+    let other = store
+        .bucket::<Integer, String>(Some("cached_bucket_many_writes"))
+        .unwrap();
+
+    // More writes than sled buffers for a watch, without calling `get`
+    let (tx, rx) = std::sync::mpsc::channel();
+    let handle = std::thread::spawn(move || {
+        for i in 0..2000u64 {
+            bucket.set(&i.into(), &i.to_string()).unwrap();
+        }
+        for i in 0..2000u64 {
+            other.set(&i.into(), &"other".to_string()).unwrap();
+        }
+        tx.send(bucket).unwrap();
+    });
+
+    let bucket = rx
+        .recv_timeout(std::time::Duration::from_secs(30))
+        .expect("writes blocked");
+    handle.join().unwrap();
+    assert_eq!(
+        bucket.get(&1999u64.into()).unwrap(),
+        Some("other".to_string())
+    );
+}
+
+#[test]
+fn test_transaction_with_ids() {
+    let path = reset("transaction_with_ids");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store
+        .bucket::<Integer, String>(Some("transaction_with_ids"))
+        .unwrap();
+
+    let attempts = std::cell::Cell::new(0);
+    let ((), ids) = bucket
+        .transaction_with_ids(|txn| {
+            attempts.set(attempts.get() + 1);
+            let a = txn.generate_id()?;
+            if attempts.get() == 1 {
+                return Err(TransactionError::Conflict);
+            }
+            let b = txn.generate_id()?;
+            txn.set(&a.into(), &"first".to_string())?;
+            txn.set(&b.into(), &"second".to_string())?;
+            Ok::<_, TransactionError<Error>>(())
+        })
+        .unwrap();
+
+    assert_eq!(ids.len(), 2);
+    assert_eq!(
+        bucket.get(&ids[0].into()).unwrap(),
+        Some("first".to_string())
+    );
+    assert_eq!(
+        bucket.get(&ids[1].into()).unwrap(),
+        Some("second".to_string())
+    );
+    assert_eq!(bucket.len(), 2);
+}
+
+#[test]
+fn test_array_keys() {
+    let path = reset("array_keys");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+
+    let flags = store.bucket::<Bool, String>(Some("flags")).unwrap();
+    flags.set(&true.into(), &"yes".to_string()).unwrap();
+    flags.set(&false.into(), &"no".to_string()).unwrap();
+    let keys: Vec<bool> = flags
+        .key_vec()
+        .unwrap()
+        .into_iter()
+        .map(bool::from)
+        .collect();
+    assert_eq!(keys, vec![false, true]);
+    assert_eq!(flags.get(&true.into()).unwrap(), Some("yes".to_string()));
+
+    let chars = store.bucket::<Char, String>(Some("chars")).unwrap();
+    for c in ['z', 'a', '\u{1F600}', 'é', 'A'] {
+        chars.set(&c.into(), &c.to_string()).unwrap();
+    }
+    let keys: Vec<char> = chars
+        .key_vec()
+        .unwrap()
+        .into_iter()
+        .map(char::from)
+        .collect();
+    assert_eq!(keys, vec!['A', 'a', 'z', 'é', '\u{1F600}']);
+
+    // Invalid bytes are rejected when decoding
+    let raw = store.bucket::<Raw, String>(Some("flags")).unwrap();
+    raw.set(&Raw::from(&[2u8]), &"bad".to_string()).unwrap();
+    assert!(matches!(flags.key_vec(), Err(Error::Conversion(_))));
+    raw.remove(&Raw::from(&[2u8])).unwrap();
+    raw.set(&Raw::from(&[0u8, 1]), &"bad".to_string()).unwrap();
+    assert!(matches!(
+        flags.key_vec(),
+        Err(Error::InvalidLength {
+            expected: 1,
+            found: 2
+        })
+    ));
+
+    let raw = store.bucket::<Raw, String>(Some("chars")).unwrap();
+    raw.set(&Raw::from(&0xd800u32.to_be_bytes()), &"bad".to_string())
+        .unwrap();
+    assert!(matches!(chars.key_vec(), Err(Error::Conversion(_))));
+    raw.remove(&Raw::from(&0xd800u32.to_be_bytes())).unwrap();
+
+    let arrays = store.bucket::<[u8; 4], String>(Some("chars")).unwrap();
+    assert_eq!(arrays.key_vec().unwrap().len(), 5);
+    raw.set(&Raw::from(b"ab"), &"bad".to_string()).unwrap();
+    assert!(matches!(
+        chars.key_vec(),
+        Err(Error::InvalidLength {
+            expected: 4,
+            found: 2
+        })
+    ));
+    assert!(matches!(
+        arrays.key_vec(),
+        Err(Error::InvalidLength {
+            expected: 4,
+            found: 2
+        })
+    ));
+}
+
+#[test]
+fn test_peek_stack_queue() {
+    let path = reset("peek_stack_queue");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let stack = store.bucket::<Integer, String>(Some("stack")).unwrap();
+
+    assert!(stack.peek_back().unwrap().is_none());
+    for s in ["a", "b", "c"] {
+        let id = store.generate_id().unwrap();
+        stack.set(&id.into(), &s.to_string()).unwrap();
+    }
+
+    let mut popped = Vec::new();
+    while let Some(top) = stack.peek_back().unwrap() {
+        let value: String = top.value().unwrap();
+        assert_eq!(stack.len(), 3 - popped.len());
+        let item = stack.pop_back().unwrap().unwrap();
+        assert_eq!(item.value::<String>().unwrap(), value);
+        popped.push(value);
+    }
+    assert_eq!(popped, vec!["c", "b", "a"]);
+
+    for s in ["a", "b"] {
+        let id = store.generate_id().unwrap();
+        stack.set(&id.into(), &s.to_string()).unwrap();
+    }
+    let front = stack.peek_front().unwrap().unwrap();
+    assert_eq!(front.value::<String>().unwrap(), "a");
+    assert_eq!(stack.len(), 2);
+    let item = stack.pop_front().unwrap().unwrap();
+    assert_eq!(item.value::<String>().unwrap(), "a");
+    let front = stack.peek_front().unwrap().unwrap();
+    assert_eq!(front.value::<String>().unwrap(), "b");
+}
+
+#[test]
+fn test_option_result_values() {
+    let path = reset("option_result_values");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+
+    let bucket = store
+        .bucket::<&str, Option<String>>(Some("option"))
+        .unwrap();
+    bucket.set(&"some", &Some("value".to_string())).unwrap();
+    bucket.set(&"none", &None).unwrap();
+    bucket.set(&"empty", &Some(String::new())).unwrap();
+
+    assert_eq!(
+        bucket.get(&"some").unwrap(),
+        Some(Some("value".to_string()))
+    );
+    assert_eq!(bucket.get(&"none").unwrap(), Some(None));
+    assert_eq!(bucket.get(&"empty").unwrap(), Some(Some(String::new())));
+    assert_eq!(bucket.get(&"missing").unwrap(), None);
+
+    let bucket = store
+        .bucket::<&str, Result<String, String>>(Some("result"))
+        .unwrap();
+    bucket.set(&"ok", &Ok("done".to_string())).unwrap();
+    bucket.set(&"err", &Err("failed".to_string())).unwrap();
+    assert_eq!(bucket.get(&"ok").unwrap(), Some(Ok("done".to_string())));
+    assert_eq!(bucket.get(&"err").unwrap(), Some(Err("failed".to_string())));
+
+    assert!(Option::<String>::from_raw_value(Raw::from(b"")).is_err());
+    assert!(Option::<String>::from_raw_value(Raw::from(b"\x02x")).is_err());
+}
+
+#[test]
+fn test_page_prefix() {
+    let path = reset("page_prefix");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<String, String>(Some("page_prefix")).unwrap();
+
+    for i in 0..50 {
+        bucket
+            .set(&format!("user/{:03}", i), &i.to_string())
+            .unwrap();
+    }
+    bucket
+        .set(&"admin/1".to_string(), &"x".to_string())
+        .unwrap();
+    bucket.set(&"zzz".to_string(), &"x".to_string()).unwrap();
+
+    let prefix = "user/".to_string();
+    let mut seen = Vec::new();
+    let mut after = None;
+    let mut pages = 0;
+    loop {
+        let (items, cursor) = bucket.page_prefix(&prefix, after.as_ref(), 20).unwrap();
+        pages += 1;
+        for item in items {
+            seen.push(item.key::<String>().unwrap());
+        }
+        match cursor {
+            Some(c) => after = Some(c),
+            None => break,
+        }
+    }
+
+    assert_eq!(pages, 3);
+    let expected: Vec<String> = (0..50).map(|i| format!("user/{:03}", i)).collect();
+    assert_eq!(seen, expected);
+
+    let (items, cursor) = bucket.page_prefix(&prefix, None, 50).unwrap();
+    assert_eq!(items.len(), 50);
+    assert!(cursor.is_none());
+
+    assert!(bucket.page_prefix(&prefix, None, 0).is_err());
+}
+
+#[test]
+fn test_checked_value() {
+    let path = reset("checked_value");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store
+        .bucket::<&str, Checked<String>>(Some("checked_value"))
+        .unwrap();
+    let raw = store.bucket::<&str, Raw>(Some("checked_value")).unwrap();
+
+    bucket.set(&"a", &Checked("important".to_string())).unwrap();
+    assert_eq!(
+        bucket.get(&"a").unwrap(),
+        Some(Checked("important".to_string()))
+    );
+
+    // Flip a bit in the stored value
+    let mut bytes = raw.get(&"a").unwrap().unwrap().to_vec();
+    bytes[0] ^= 0x01;
+    raw.set(&"a", &Raw::from(bytes)).unwrap();
+
+    assert!(raw.get(&"a").is_ok());
+    assert!(matches!(
+        bucket.get(&"a"),
+        Err(Error::ChecksumMismatch { .. })
+    ));
+}
+
+#[test]
+fn test_deferred_value() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static DECODED: AtomicUsize = AtomicUsize::new(0);
+
+    struct Counted(String);
+
+    impl Value for Counted {
+        fn to_raw_value(&self) -> Result<Raw, Error> {
+            self.0.to_raw_value()
+        }
+
+        fn from_raw_value(r: Raw) -> Result<Self, Error> {
+            DECODED.fetch_add(1, Ordering::SeqCst);
+            Ok(Counted(String::from_raw_value(r)?))
+        }
+    }
+
+    let path = reset("deferred_value");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store
+        .bucket::<Integer, Deferred<Counted>>(Some("deferred_value"))
+        .unwrap();
+
+    for i in 0..100u64 {
+        let value = Deferred::new(&Counted(i.to_string())).unwrap();
+        bucket.set(&i.into(), &value).unwrap();
+    }
+
+    let mut values = Vec::new();
+    for (i, item) in bucket.iter().enumerate() {
+        let deferred: Deferred<Counted> = item.unwrap().value().unwrap();
+        if i % 10 == 0 {
+            values.push(deferred.get().unwrap().0);
+        }
+    }
+
+    assert_eq!(values.len(), 10);
+    assert_eq!(values[3], "30");
+    assert_eq!(DECODED.load(Ordering::SeqCst), 10);
+}
+
+#[test]
+fn test_store_rename() {
+    let path = reset("store_rename");
+    let new_path = reset("store_rename_moved");
+    let cfg = Config::new(path.clone());
+    let mut store = Store::new(cfg).unwrap();
+    {
+        let bucket = store.bucket::<&str, String>(Some("data")).unwrap();
+        bucket.set(&"a", &"1".to_string()).unwrap();
+    }
+
+    store.rename(&new_path).unwrap();
+    assert_eq!(store.path().unwrap(), path::Path::new(&new_path));
+    assert!(!path::Path::new(&path).exists());
+    let bucket = store.bucket::<&str, String>(Some("data")).unwrap();
+    assert_eq!(bucket.get(&"a").unwrap(), Some("1".to_string()));
+    drop(bucket);
+
+    // Moving onto an existing path fails without touching the store
+    fs::create_dir_all(&path).unwrap();
+    assert!(store.rename(&path).is_err());
+    assert_eq!(store.path().unwrap(), path::Path::new(&new_path));
+    let bucket = store.bucket::<&str, String>(Some("data")).unwrap();
+    assert_eq!(bucket.get(&"a").unwrap(), Some("1".to_string()));
+    bucket.set(&"b", &"2".to_string()).unwrap();
+    drop(bucket);
+
+    // Renaming repeatedly doesn't trip over the lock held briefly by the closed database
+    fs::remove_dir_all(&path).unwrap();
+    for _ in 0..5 {
+        store.rename(&path).unwrap();
+        store.rename(&new_path).unwrap();
+    }
+    let bucket = store.bucket::<&str, String>(Some("data")).unwrap();
+    assert_eq!(bucket.get(&"b").unwrap(), Some("2".to_string()));
+}
+
+#[cfg(feature = "json-value")]
+#[test]
+fn test_get_as() {
+    use crate::Json;
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+    struct Testing {
+        a: i32,
+    }
+
+    let path = reset("get_as");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, Raw>(Some("get_as")).unwrap();
+
+    bucket
+        .set(&"json", &Json(Testing { a: 1 }).to_raw_value().unwrap())
+        .unwrap();
+    bucket.set(&"text", &Raw::from(b"hello")).unwrap();
+
+    let v = bucket.get_as::<Json<Testing>>(&"json").unwrap().unwrap();
+    assert_eq!(v.0, Testing { a: 1 });
+    assert_eq!(
+        bucket.get_as::<String>(&"text").unwrap(),
+        Some("hello".to_string())
+    );
+    assert!(bucket.get_as::<Json<Testing>>(&"text").is_err());
+    assert!(bucket
+        .get_as::<Json<Testing>>(&"missing")
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn test_counters() {
+    let path = reset("counters");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let counters = store.counters("counters").unwrap();
+
+    assert_eq!(counters.get(&"missing").unwrap(), 0);
+
+    let handles: Vec<_> = (0..8)
+        .map(|t| {
+            let counters = counters.clone();
+            std::thread::spawn(move || {
+                for _ in 0..100 {
+                    counters.incr(&"hits", 1).unwrap();
+                    counters.incr(&"bytes", 10).unwrap();
+                    counters.decr(&"balance", t).unwrap();
+                }
+            })
+        })
+        .collect();
+    for h in handles {
+        h.join().unwrap();
+    }
+
+    assert_eq!(counters.get(&"hits").unwrap(), 800);
+    assert_eq!(counters.get(&"bytes").unwrap(), 8000);
+    assert_eq!(counters.get(&"balance").unwrap(), -2800);
+
+    assert_eq!(counters.reset(&"hits").unwrap(), 800);
+    assert_eq!(counters.get(&"hits").unwrap(), 0);
+    assert_eq!(counters.incr(&"hits", 5).unwrap(), 5);
+
+    counters.incr(&"max", i64::MAX).unwrap();
+    assert!(counters.incr(&"max", 1).is_err());
+}
+
+#[test]
+fn test_max_transaction_retries() {
+    let path = reset("max_transaction_retries");
+    let cfg = Config::new(path.clone()).max_transaction_retries(3);
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, String>(Some("test")).unwrap();
+
+    // Transactions that succeed are unaffected by the limit
+    bucket
+        .transaction(|txn| {
+            txn.set(&"n", &"0".to_string())?;
+            Ok::<_, TransactionError<Error>>(())
+        })
+        .unwrap();
+
+    // sled serializes transactions, so contention is simulated by reporting a conflict on
+    // every attempt from several threads at once
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            let bucket = bucket.clone();
+            std::thread::spawn(move || {
+                let attempts = std::sync::atomic::AtomicU32::new(0);
+                let res = bucket.transaction(|txn| {
+                    attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    let n: u64 = txn.get(&"n")?.unwrap().parse().unwrap();
+                    txn.set(&"n", &(n + 1).to_string())?;
+                    Err::<(), _>(TransactionError::Conflict)
+                });
+                assert!(matches!(res, Err(Error::TransactionRetryExhausted)));
+                attempts.into_inner()
+            })
+        })
+        .collect();
+    for h in handles {
+        assert_eq!(h.join().unwrap(), 3);
+    }
+
+    assert_eq!(bucket.get(&"n").unwrap().unwrap(), "0");
+}
+
+#[test]
+fn test_export_stable() {
+    let path = reset("export_stable_src");
+    let cfg = Config::new(path.clone());
+    let src = Store::new(cfg).unwrap();
+    let a = src.bucket::<&str, String>(Some("a")).unwrap();
+    let b = src.bucket::<Integer, Raw>(Some("b")).unwrap();
+    for i in 0..100u64 {
+        a.set(&format!("key{}", i).as_str(), &format!("value{}", i))
+            .unwrap();
+        b.set(&i.into(), &Raw::from(vec![i as u8; i as usize % 5]))
+            .unwrap();
+    }
+
+    let mut first = Vec::new();
+    src.export_stable(&mut first).unwrap();
+    let mut second = Vec::new();
+    src.export_stable(&mut second).unwrap();
+    assert_eq!(first, second);
+    assert!(std::str::from_utf8(&first)
+        .unwrap()
+        .lines()
+        .any(|line| line == "[YQ==]"));
+
+    let path = reset("export_stable_dest");
+    let cfg = Config::new(path.clone());
+    let dest = Store::new(cfg).unwrap();
+    dest.import_stable(first.as_slice()).unwrap();
+
+    let a2 = dest.bucket::<&str, String>(Some("a")).unwrap();
+    let b2 = dest.bucket::<Integer, Raw>(Some("b")).unwrap();
+    assert_eq!(a2.checksum().unwrap(), a.checksum().unwrap());
+    assert_eq!(b2.checksum().unwrap(), b.checksum().unwrap());
+    assert_eq!(b2.get(&3.into()).unwrap().unwrap(), Raw::from(vec![3u8; 3]));
+
+    let mut third = Vec::new();
+    dest.export_stable(&mut third).unwrap();
+    assert_eq!(first, third);
+
+    assert!(dest.import_stable(&b"garbage"[..]).is_err());
+}
+
+#[test]
+fn test_decoded_event() {
+    let path = reset("decoded_event");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<String, String>(Some("test")).unwrap();
+
+    let (tx, rx) = std::sync::mpsc::channel::<DecodedEvent<String, String>>();
+    let handle = std::thread::spawn(move || rx.iter().take(2).collect::<Vec<_>>());
+
+    let mut watch = bucket.watch_prefix(None).unwrap();
+    bucket.set(&"a".to_string(), &"1".to_string()).unwrap();
+    bucket.remove(&"a".to_string()).unwrap();
+    for _ in 0..2 {
+        let event = watch.next().unwrap().unwrap();
+        tx.send(event.into_decoded().unwrap()).unwrap();
+    }
+
+    let events = handle.join().unwrap();
+    assert_eq!(
+        events,
+        vec![
+            DecodedEvent {
+                key: "a".to_string(),
+                value: Some("1".to_string())
+            },
+            DecodedEvent {
+                key: "a".to_string(),
+                value: None
+            },
+        ]
+    );
+    assert!(events[1].is_remove());
+}
+
+#[test]
+fn test_sum_prefix() {
+    let path = reset("sum_prefix");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, u64>(Some("spend")).unwrap();
+
+    bucket.set(&"alice/1", &10).unwrap();
+    bucket.set(&"alice/2", &25).unwrap();
+    bucket.set(&"alice/3", &7).unwrap();
+    bucket.set(&"alicia/1", &1000).unwrap();
+    bucket.set(&"bob/1", &100).unwrap();
+
+    assert_eq!(bucket.sum_prefix::<u64>(&"alice/").unwrap(), 42);
+    assert_eq!(bucket.sum_prefix::<u64>(&"bob/").unwrap(), 100);
+    assert_eq!(bucket.sum_prefix::<u64>(&"carol/").unwrap(), 0);
+    assert_eq!(bucket.get(&"bob/1").unwrap(), Some(100));
+}
+
+#[test]
+fn test_batch_get() {
+    let path = reset("batch_get");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, String>(Some("test")).unwrap();
+    bucket.set(&"existing", &"old".to_string()).unwrap();
+
+    let mut batch = Batch::new();
+    batch.set(&"a", &"1".to_string()).unwrap();
+    assert_eq!(batch.get(&"a").unwrap(), Some("1".to_string()));
+    batch.set(&"a", &"2".to_string()).unwrap();
+    assert_eq!(batch.get(&"a").unwrap(), Some("2".to_string()));
+
+    // Reads never fall through to the bucket
+    assert_eq!(batch.get(&"existing").unwrap(), None);
+    batch.set(&"existing", &"new".to_string()).unwrap();
+    batch.remove(&"existing").unwrap();
+    assert_eq!(batch.get(&"existing").unwrap(), None);
+
+    assert!(bucket.get(&"a").unwrap().is_none());
+    bucket.batch(batch).unwrap();
+    assert_eq!(bucket.get(&"a").unwrap(), Some("2".to_string()));
+    assert!(bucket.get(&"existing").unwrap().is_none());
+}
+
+#[test]
+fn test_open_read_only() {
+    let path = reset("open_read_only");
+    let cfg = Config::new(path.clone());
+    let writer = Store::new(cfg.clone()).unwrap();
+    let bucket = writer.bucket::<&str, String>(Some("test")).unwrap();
+    bucket.set(&"a", &"1".to_string()).unwrap();
+    bucket.flush().unwrap();
+
+    let reader = Store::open_read_only(cfg).unwrap();
+    assert!(reader.is_read_only());
+    let view = reader.bucket::<&str, String>(Some("test")).unwrap();
+    assert_eq!(view.get(&"a").unwrap(), Some("1".to_string()));
+
+    assert!(matches!(
+        view.set(&"b", &"2".to_string()),
+        Err(Error::ReadOnly)
+    ));
+    assert!(matches!(view.remove(&"a"), Err(Error::ReadOnly)));
+    assert!(matches!(view.clear(), Err(Error::ReadOnly)));
+    assert!(matches!(
+        view.transaction(|txn| {
+            txn.set(&"b", &"2".to_string())?;
+            Ok::<_, TransactionError<Error>>(())
+        }),
+        Err(Error::ReadOnly)
+    ));
+    assert!(matches!(reader.drop_bucket("test"), Err(Error::ReadOnly)));
+    assert!(matches!(
+        reader.import(writer.export()),
+        Err(Error::ReadOnly)
+    ));
+    assert_eq!(reader.path().unwrap(), path::Path::new(&path));
+    assert!(matches!(
+        reader.counters("counters").unwrap().incr(&"n", 1),
+        Err(Error::ReadOnly)
+    ));
+
+    // The writer is unaffected and its later writes are not visible to the reader
+    bucket.set(&"b", &"2".to_string()).unwrap();
+    assert_eq!(view.get(&"b").unwrap(), None);
+    assert_eq!(view.get(&"a").unwrap(), Some("1".to_string()));
+}
+
+#[test]
+fn test_os_key() {
+    let path = reset("os_key");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<OsKey, String>(Some("files")).unwrap();
+
+    let p = std::path::PathBuf::from("/tmp/some file.txt");
+    bucket.set(&p.clone().into(), &"utf8".to_string()).unwrap();
+    assert_eq!(
+        bucket.get(&p.as_path().into()).unwrap(),
+        Some("utf8".to_string())
+    );
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        let p = std::path::Path::new(std::ffi::OsStr::from_bytes(b"/tmp/\xff\xfe.bin"));
+        bucket.set(&p.into(), &"non-utf8".to_string()).unwrap();
+
+        let keys = bucket
+            .iter()
+            .map(|item| item.unwrap().key::<OsKey>().unwrap().into_path_buf())
+            .collect::<Vec<_>>();
+        assert!(keys.iter().any(|k| k == p));
+        assert!(keys
+            .iter()
+            .any(|k| k.as_os_str().as_bytes() == b"/tmp/\xff\xfe.bin"));
+    }
+}
+
+#[test]
+fn test_rekey() {
+    let path = reset("rekey");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<String, String>(Some("test")).unwrap();
+
+    for i in 0..10 {
+        bucket
+            .set(&format!("old/{}", i), &format!("value{}", i))
+            .unwrap();
+    }
+    bucket.set(&"other".to_string(), &"x".to_string()).unwrap();
+
+    let n = bucket
+        .rekey(|k| match k.strip_prefix("old/") {
+            Some(rest) => Ok(format!("new/{}", rest)),
+            None => Ok(k.clone()),
+        })
+        .unwrap();
+    assert_eq!(n, 10);
+    assert_eq!(bucket.len(), 11);
+    for i in 0..10 {
+        assert!(bucket.get(&format!("old/{}", i)).unwrap().is_none());
+        assert_eq!(
+            bucket.get(&format!("new/{}", i)).unwrap(),
+            Some(format!("value{}", i))
+        );
+    }
+    assert_eq!(bucket.get(&"other".to_string()).unwrap(), Some("x".into()));
+
+    // Collisions are rejected without changing anything
+    assert!(bucket.rekey(|_| Ok("same".to_string())).is_err());
+    assert_eq!(bucket.len(), 11);
+    assert!(bucket.get(&"same".to_string()).unwrap().is_none());
+}
+
+#[test]
+fn test_tuple_value() {
+    let path = reset("tuple_value");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+
+    let pairs = store.bucket::<&str, (u64, String)>(Some("pairs")).unwrap();
+    pairs.set(&"a", &(7, "seven".to_string())).unwrap();
+    pairs.set(&"b", &(0, String::new())).unwrap();
+    assert_eq!(pairs.get(&"a").unwrap(), Some((7, "seven".to_string())));
+    assert_eq!(pairs.get(&"b").unwrap(), Some((0, String::new())));
+
+    let triples = store
+        .bucket::<&str, (String, Vec<u8>, u64)>(Some("triples"))
+        .unwrap();
+    let value = ("name".to_string(), vec![0, 1, 2, 255], 42);
+    triples.set(&"a", &value).unwrap();
+    assert_eq!(triples.get(&"a").unwrap(), Some(value));
+
+    // Truncated or trailing data is rejected
+    let raw = store.bucket::<&str, Raw>(Some("pairs")).unwrap();
+    let a = raw.get(&"a").unwrap().unwrap();
+    raw.set(&"short", &Raw::from(&a[..a.len() - 1])).unwrap();
+    assert!(pairs.get(&"short").is_err());
+    let mut long = a.to_vec();
+    long.push(0);
+    raw.set(&"long", &Raw::from(long)).unwrap();
+    assert!(pairs.get(&"long").is_err());
+}
+
+#[test]
+fn test_watch_prefix_buffered() {
+    let path = reset("watch_prefix_buffered");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<String, String>(Some("test")).unwrap();
+
+    let mut watch = bucket
+        .watch_prefix_buffered(None, 2, Overflow::Drop)
+        .unwrap();
+    for i in 0..50 {
+        bucket.set(&format!("key{}", i), &i.to_string()).unwrap();
+    }
+
+    // The consumer hasn't read anything yet, so everything after the first two events is dropped
+    let start = std::time::Instant::now();
+    while watch.dropped() < 48 && start.elapsed() < std::time::Duration::from_secs(10) {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert_eq!(watch.dropped(), 48);
+
+    let first = watch.next().unwrap();
+    assert_eq!(first.key().unwrap(), "key0");
+    let second = watch.next().unwrap();
+    assert_eq!(second.key().unwrap(), "key1");
+    assert!(watch
+        .next_timeout(std::time::Duration::from_millis(50))
+        .is_none());
+
+    // With `Overflow::Block` nothing is dropped
+    let mut watch = bucket
+        .watch_prefix_buffered(None, 1, Overflow::Block)
+        .unwrap();
+    for i in 0..10 {
+        bucket.set(&format!("key{}", i), &"x".to_string()).unwrap();
+    }
+    for i in 0..10 {
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert_eq!(watch.next().unwrap().key().unwrap(), format!("key{}", i));
+    }
+    assert_eq!(watch.dropped(), 0);
+
+    for overflow in [Overflow::Drop, Overflow::Block] {
+        assert!(matches!(
+            bucket.watch_prefix_buffered(None, 0, overflow),
+            Err(Error::InvalidConfiguration)
+        ));
+    }
+}
+
+#[test]
+fn test_update2() {
+    let path = reset("update2");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let checking = store.bucket::<&str, u64>(Some("checking")).unwrap();
+    let savings = store.bucket::<&str, u64>(Some("savings")).unwrap();
+    checking.set(&"alice", &1000).unwrap();
+    savings.set(&"alice", &1000).unwrap();
+
+    let handles: Vec<_> = (0..4)
+        .map(|t| {
+            let checking = checking.clone();
+            let savings = savings.clone();
+            std::thread::spawn(move || {
+                for i in 0..50u64 {
+                    let amount = (i % 7) + 1;
+                    let (a, b) = if t % 2 == 0 {
+                        (&checking, &savings)
+                    } else {
+                        (&savings, &checking)
+                    };
+                    a.update2(b, &"alice", &"alice", |x, y| {
+                        let (x, y) = (x.unwrap_or(0), y.unwrap_or(0));
+                        if x < amount {
+                            return (Some(x), Some(y));
+                        }
+                        (Some(x - amount), Some(y + amount))
+                    })
+                    .unwrap();
+                }
+            })
+        })
+        .collect();
+    for h in handles {
+        h.join().unwrap();
+    }
+
+    let total = checking.get(&"alice").unwrap().unwrap() + savings.get(&"alice").unwrap().unwrap();
+    assert_eq!(total, 2000);
+
+    let (a, b) = checking
+        .update2(&savings, &"alice", &"bob", |x, _| (None, x))
+        .unwrap();
+    assert!(a.is_none());
+    assert!(checking.get(&"alice").unwrap().is_none());
+    assert_eq!(savings.get(&"bob").unwrap(), b);
+}
+
+#[test]
+fn test_flush_bucket() {
+    let path = reset("flush_bucket");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, String>(Some("maintenance")).unwrap();
+    bucket.set(&"a", &"1".to_string()).unwrap();
+
+    assert!(store.flush_bucket("maintenance").unwrap() > 0);
+    assert_eq!(store.flush_bucket("maintenance").unwrap(), 0);
+    assert_eq!(bucket.get(&"a").unwrap(), Some("1".to_string()));
+}
+
+#[test]
+fn test_invalid_utf8_bytes() {
+    let path = reset("invalid_utf8_bytes");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let raw = store.bucket::<Raw, Raw>(Some("test")).unwrap();
+    raw.set(&Raw::from(&b"key"[..]), &Raw::from(&b"bad \xff"[..]))
+        .unwrap();
+    raw.set(&Raw::from(&b"\xfe"[..]), &Raw::from(&b"ok"[..]))
+        .unwrap();
+
+    let bucket = store.bucket::<String, String>(Some("test")).unwrap();
+    match bucket.get(&"key".to_string()) {
+        Err(Error::InvalidUtf8 { bytes, .. }) => assert_eq!(bytes, b"bad \xff"),
+        _ => panic!("expected InvalidUtf8"),
+    }
+
+    for item in bucket.iter() {
+        let item = item.unwrap();
+        match (item.key::<String>(), item.value::<String>()) {
+            (Ok(k), Err(Error::InvalidUtf8 { bytes, .. })) => {
+                assert_eq!(k, "key");
+                assert_eq!(bytes, b"bad \xff");
+            }
+            (Err(Error::InvalidUtf8 { bytes, .. }), Ok(v)) => {
+                assert_eq!(bytes, b"\xfe");
+                assert_eq!(v, "ok");
+            }
+            _ => panic!("expected InvalidUtf8"),
+        }
+    }
+}
+
+#[test]
+fn test_apply_ops() {
+    let path = reset("apply_ops");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, String>(Some("test")).unwrap();
+    bucket.set(&"a", &"1".to_string()).unwrap();
+    bucket.set(&"b", &"2".to_string()).unwrap();
+
+    bucket
+        .apply_ops(vec![
+            Op::Set("c", "3".to_string()),
+            Op::Remove("b"),
+            Op::Cas {
+                key: "a",
+                old: Some("1".to_string()),
+                new: Some("10".to_string()),
+            },
+            Op::Cas {
+                key: "d",
+                old: None,
+                new: Some("4".to_string()),
+            },
+        ])
+        .unwrap();
+    assert_eq!(bucket.get(&"a").unwrap(), Some("10".to_string()));
+    assert!(bucket.get(&"b").unwrap().is_none());
+    assert_eq!(bucket.get(&"c").unwrap(), Some("3".to_string()));
+    assert_eq!(bucket.get(&"d").unwrap(), Some("4".to_string()));
+
+    // A failing CAS rolls back the operations before it
+    let res = bucket.apply_ops(vec![
+        Op::Set("e", "5".to_string()),
+        Op::Remove("c"),
+        Op::Cas {
+            key: "a",
+            old: Some("1".to_string()),
+            new: None,
+        },
+    ]);
+    assert!(matches!(res, Err(Error::CompareAndSwap(_))));
+    assert!(bucket.get(&"e").unwrap().is_none());
+    assert_eq!(bucket.get(&"c").unwrap(), Some("3".to_string()));
+    assert_eq!(bucket.get(&"a").unwrap(), Some("10".to_string()));
+}
+
+#[test]
+fn test_bucket_sizes() {
+    let path = reset("bucket_sizes");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let small = store.bucket::<Integer, Raw>(Some("small")).unwrap();
+    let large = store.bucket::<Integer, Raw>(Some("large")).unwrap();
+    store.bucket::<Integer, Raw>(Some("empty")).unwrap();
+    for i in 0..100u64 {
+        small.set(&i.into(), &Raw::from(vec![0u8; 16])).unwrap();
+        large.set(&i.into(), &Raw::from(vec![0u8; 1024])).unwrap();
+    }
+    store.flush_bucket("large").unwrap();
+
+    let sizes = store.bucket_sizes().unwrap();
+    assert!(sizes["large"] > sizes["small"]);
+    assert!(sizes["small"] > 0);
+    assert_eq!(sizes["empty"], 0);
+    assert!(sizes.values().sum::<u64>() <= store.size_on_disk().unwrap());
+}
+
+#[test]
+fn test_enum8() {
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Status {
+        Pending,
+        Active,
+        Closed,
+    }
+
+    impl ByteEnum for Status {
+        fn to_u8(&self) -> u8 {
+            *self as u8
+        }
+
+        fn from_u8(x: u8) -> Option<Self> {
+            match x {
+                0 => Some(Status::Pending),
+                1 => Some(Status::Active),
+                2 => Some(Status::Closed),
+                _ => None,
+            }
+        }
+    }
+
+    let path = reset("enum8");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, Enum8<Status>>(Some("status")).unwrap();
+
+    for (k, s) in [
+        ("a", Status::Pending),
+        ("b", Status::Active),
+        ("c", Status::Closed),
+    ] {
+        bucket.set(&k, &Enum8(s)).unwrap();
+        assert_eq!(bucket.get(&k).unwrap(), Some(Enum8(s)));
+    }
+
+    let raw = store.bucket::<&str, Raw>(Some("status")).unwrap();
+    assert_eq!(raw.get(&"b").unwrap().unwrap().as_ref(), &[1]);
+    assert!(raw
+        .iter()
+        .all(|item| item.unwrap().value::<Raw>().unwrap().len() == 1));
+
+    raw.set(&"d", &Raw::from(&[3u8][..])).unwrap();
+    assert!(bucket.get(&"d").is_err());
+}
+
+#[test]
+fn test_iter_prefixes() {
+    let path = reset("iter_prefixes");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<String, String>(Some("test")).unwrap();
+    for entity in ["a", "b", "c", "d"] {
+        for i in 0..5 {
+            let k = format!("{}/{}", entity, i);
+            bucket.set(&k, &k).unwrap();
+        }
+    }
+
+    let prefixes = [
+        "c/".to_string(),
+        "a/".to_string(),
+        "d/".to_string(),
+        "d/1".to_string(),
+    ];
+    let keys = bucket
+        .iter_prefixes(&prefixes)
+        .unwrap()
+        .map(|item| item.unwrap().key::<String>().unwrap())
+        .collect::<Vec<_>>();
+
+    let mut expected = Vec::new();
+    for entity in ["a", "c", "d"] {
+        for i in 0..5 {
+            expected.push(format!("{}/{}", entity, i));
+        }
+    }
+    assert_eq!(keys, expected);
+}
+
+#[test]
+fn test_replace() {
+    let path = reset("replace");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, String>(Some("test")).unwrap();
+    bucket.set(&"a", &"1".to_string()).unwrap();
+
+    assert_eq!(bucket.replace(&"a", &"2".to_string()).unwrap(), "1");
+    assert_eq!(bucket.get(&"a").unwrap(), Some("2".to_string()));
+
+    assert!(matches!(
+        bucket.replace(&"missing", &"x".to_string()),
+        Err(Error::NotFound)
+    ));
+    assert!(bucket.get(&"missing").unwrap().is_none());
+}
+
+#[test]
+fn test_migrator() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let path = reset("migrator");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let runs = Arc::new([AtomicUsize::new(0), AtomicUsize::new(0)]);
+
+    let migrator = || {
+        let a = runs.clone();
+        let b = runs.clone();
+        Migrator::new()
+            .step(move |store| {
+                a[0].fetch_add(1, Ordering::SeqCst);
+                let users = store.bucket::<&str, String>(Some("users"))?;
+                users.set(&"admin", &"root".to_string())?;
+                Ok(())
+            })
+            .step(move |store| {
+                b[1].fetch_add(1, Ordering::SeqCst);
+                let users = store.bucket::<&str, String>(Some("users"))?;
+                let name = users.get(&"admin")?.unwrap_or_default();
+                users.set(&"admin", &name.to_uppercase())?;
+                Ok(())
+            })
+    };
+
+    assert_eq!(Migrator::version(&store).unwrap(), 0);
+    assert_eq!(migrator().run(&store).unwrap(), 2);
+    assert_eq!(migrator().run(&store).unwrap(), 2);
+    assert_eq!(Migrator::version(&store).unwrap(), 2);
+    assert_eq!(runs[0].load(Ordering::SeqCst), 1);
+    assert_eq!(runs[1].load(Ordering::SeqCst), 1);
+
+    let users = store.bucket::<&str, String>(Some("users")).unwrap();
+    assert_eq!(users.get(&"admin").unwrap(), Some("ROOT".to_string()));
+
+    // A store migrated by a newer version is rejected
+    assert!(Migrator::new().run(&store).is_err());
+}
+
+#[test]
+fn test_range_value() {
+    let path = reset("range_value");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+
+    let ranges = store
+        .bucket::<&str, std::ops::Range<u64>>(Some("ranges"))
+        .unwrap();
+    ranges.set(&"a", &(10..20)).unwrap();
+    ranges.set(&"empty", &(5..5)).unwrap();
+    ranges.set(&"max", &(0..u64::MAX)).unwrap();
+    assert_eq!(ranges.get(&"a").unwrap(), Some(10..20));
+    assert_eq!(ranges.get(&"empty").unwrap(), Some(5..5));
+    assert_eq!(ranges.get(&"max").unwrap(), Some(0..u64::MAX));
+
+    let inclusive = store
+        .bucket::<&str, std::ops::RangeInclusive<u64>>(Some("inclusive"))
+        .unwrap();
+    inclusive.set(&"a", &(10..=20)).unwrap();
+    inclusive.set(&"max", &(u64::MAX..=u64::MAX)).unwrap();
+    assert_eq!(inclusive.get(&"a").unwrap(), Some(10..=20));
+    assert_eq!(inclusive.get(&"max").unwrap(), Some(u64::MAX..=u64::MAX));
+
+    let raw = store.bucket::<&str, Raw>(Some("ranges")).unwrap();
+    assert_eq!(raw.get(&"a").unwrap().unwrap().len(), 16);
+}
+
+#[test]
+fn test_migrate_integer_keys() {
+    let path = reset("migrate_integer_keys");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let raw = store.bucket::<Raw, String>(Some("legacy")).unwrap();
+    for i in [1u64, 2, 300, u64::MAX] {
+        raw.set(&Raw::from(&i.to_be_bytes()), &i.to_string())
+            .unwrap();
+    }
+    raw.set(&Raw::from(&b"other"[..]), &"x".to_string())
+        .unwrap();
+
+    let bucket = store.bucket::<Integer, String>(Some("legacy")).unwrap();
+    assert!(matches!(
+        bucket.iter().next().unwrap().unwrap().key::<Integer>(),
+        Err(Error::InvalidLength { .. })
+    ));
+
+    assert_eq!(store.migrate_integer_keys("legacy").unwrap(), 4);
+    for i in [1u64, 2, 300, u64::MAX] {
+        assert_eq!(bucket.get(&i.into()).unwrap(), Some(i.to_string()));
+        assert!(raw.get(&Raw::from(&i.to_be_bytes())).unwrap().is_none());
+    }
+    assert_eq!(
+        raw.get(&Raw::from(&b"other"[..])).unwrap(),
+        Some("x".into())
+    );
+    assert_eq!(store.migrate_integer_keys("legacy").unwrap(), 0);
+}
+
+#[test]
+fn test_get_map() {
+    let path = reset("get_map");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, String>(Some("test")).unwrap();
+    bucket.set(&"a", &"1".to_string()).unwrap();
+    bucket.set(&"c", &"3".to_string()).unwrap();
+
+    let map = bucket.get_map(&["a", "b", "c", "d"]).unwrap();
+    assert_eq!(map.len(), 2);
+    assert_eq!(map["a"], "1");
+    assert_eq!(map["c"], "3");
+    assert!(!map.contains_key("b"));
+    assert!(!map.contains_key("d"));
+}
+
+#[test]
+fn test_op_counts() {
+    let path = reset("op_counts");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let a = store.bucket::<&str, String>(Some("a")).unwrap();
+    let b = store.bucket::<&str, String>(Some("b")).unwrap();
+    assert_eq!(store.op_counts(), OpCounts::default());
+
+    for i in 0..5 {
+        a.set(&"x", &i.to_string()).unwrap();
+    }
+    b.set(&"y", &"1".to_string()).unwrap();
+    for _ in 0..3 {
+        a.get(&"x").unwrap();
+    }
+    b.get(&"missing").unwrap();
+    assert!(a.contains(&"x").unwrap());
+    assert_eq!(a.get_as::<Raw>(&"x").unwrap(), Some(Raw::from(b"4")));
+    a.remove(&"x").unwrap();
+    b.remove(&"y").unwrap();
+
+    a.transaction(|txn| {
+        txn.set(&"z", &"1".to_string())?;
+        Ok::<_, TransactionError<Error>>(())
+    })
+    .unwrap();
+    let attempts = std::cell::Cell::new(0);
+    b.transaction(|_| {
+        attempts.set(attempts.get() + 1);
+        if attempts.get() < 3 {
+            return Err(TransactionError::Conflict);
+        }
+        Ok::<_, TransactionError<Error>>(())
+    })
+    .unwrap();
+
+    assert_eq!(
+        store.op_counts(),
+        OpCounts {
+            gets: 6,
+            sets: 6,
+            removes: 2,
+            transactions: 2,
+            conflicts: 2,
+        }
+    );
+}
+
+#[test]
+fn test_op_counts_bulk() {
+    let path = reset("op_counts_bulk");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let a = store.bucket::<&str, String>(Some("a")).unwrap();
+    let b = store.bucket::<&str, String>(Some("b")).unwrap();
+
+    let mut batch = Batch::new();
+    batch.set(&"x", &"1".to_string()).unwrap();
+    batch.set(&"y", &"2".to_string()).unwrap();
+    batch.remove(&"z").unwrap();
+    a.batch(batch).unwrap();
+
+    a.compare_and_swap(&"x", Some(&"1".to_string()), Some(&"3".to_string()))
+        .unwrap();
+    assert!(a
+        .compare_and_swap(&"x", Some(&"1".to_string()), None)
+        .is_err());
+    a.compare_and_compute(&"y", |_| None).unwrap();
+    assert!(a.init_once(&"w", &"4".to_string()).unwrap());
+    assert!(!a.init_once(&"w", &"5".to_string()).unwrap());
+    assert_eq!(a.copy_to(&b, true).unwrap(), 2);
+    b.pop_front().unwrap();
+    b.clear().unwrap();
+
+    let before = store.op_counts();
+    a.rename_key(&"x", &"v", false).unwrap();
+    let after = store.op_counts();
+    assert_eq!((after.sets, after.removes), (before.sets, before.removes));
+    assert_eq!(after.transactions, before.transactions + 1);
+
+    assert_eq!(
+        store.op_counts(),
+        OpCounts {
+            gets: 0,
+            sets: 6,
+            removes: 5,
+            transactions: 1,
+            conflicts: 0,
+        }
+    );
+}
+
+#[test]
+fn test_copy_to() {
+    let path = reset("copy_to");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let src = store.bucket::<Integer, String>(Some("src")).unwrap();
+    let dest = store.bucket::<Integer, String>(Some("dest")).unwrap();
+    for i in 0..100u64 {
+        src.set(&i.into(), &format!("value{}", i)).unwrap();
+    }
+
+    assert_eq!(src.copy_to(&dest, false).unwrap(), 100);
+    assert_eq!(dest.len(), 100);
+    assert_eq!(dest.checksum().unwrap(), src.checksum().unwrap());
+    for i in 0..100u64 {
+        assert_eq!(dest.get(&i.into()).unwrap(), Some(format!("value{}", i)));
+    }
+
+    dest.set(&1000u64.into(), &"extra".to_string()).unwrap();
+    src.copy_to(&dest, false).unwrap();
+    assert_eq!(dest.len(), 101);
+    src.copy_to(&dest, true).unwrap();
+    assert_eq!(dest.len(), 100);
+    assert_eq!(dest.checksum().unwrap(), src.checksum().unwrap());
+}
+
+#[test]
+fn test_prefixed_bucket() {
+    let prefix = "https://example.com/some/very/long/shared/path/to/resources/";
+
+    let path = reset("prefixed_bucket");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store
+        .prefixed_bucket::<String, String>(Some("urls"), prefix.as_bytes())
+        .unwrap();
+
+    let path = reset("prefixed_bucket_plain");
+    let cfg = Config::new(path.clone());
+    let plain_store = Store::new(cfg).unwrap();
+    let plain = plain_store.bucket::<String, String>(Some("urls")).unwrap();
+
+    for i in 0..5000 {
+        let k = format!("{}{:05}", prefix, i);
+        bucket.set(&k, &i.to_string()).unwrap();
+        plain.set(&k, &i.to_string()).unwrap();
+    }
+    // Keys that don't share the prefix are stored in full, ordered around it
+    bucket.set(&"a".to_string(), &"before".to_string()).unwrap();
+    bucket.set(&"z".to_string(), &"after".to_string()).unwrap();
+
+    assert_eq!(
+        bucket.get(&format!("{}00042", prefix)).unwrap(),
+        Some("42".to_string())
+    );
+    let keys = bucket
+        .iter()
+        .map(|item| item.unwrap().key::<String>().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(keys.len(), 5002);
+    assert_eq!(keys[0], "a");
+    assert_eq!(keys[1], format!("{}00000", prefix));
+    assert_eq!(keys[5001], "z");
+    assert!(keys.windows(2).all(|w| w[0] < w[1]));
+
+    let range = bucket
+        .range(format!("{}00010", prefix)..format!("{}00013", prefix))
+        .unwrap()
+        .map(|item| item.unwrap().value::<String>().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(range, vec!["10", "11", "12"]);
+    assert_eq!(
+        bucket
+            .iter_prefix(&format!("{}0001", prefix))
+            .unwrap()
+            .count(),
+        10
+    );
+    assert_eq!(
+        bucket.iter_prefix(&"https://".to_string()).unwrap().count(),
+        5000
+    );
+    assert_eq!(bucket.iter_prefix(&String::new()).unwrap().count(), 5002);
+
+    bucket.bucket().flush().unwrap();
+    plain.flush().unwrap();
+    let stored: usize = bucket
+        .bucket()
+        .iter()
+        .map(|item| item.unwrap().key::<Raw>().unwrap().len())
+        .sum();
+    assert!(stored < 5000 * (prefix.len() + 5) / 4);
+    assert!(store.size_on_disk().unwrap() < plain_store.size_on_disk().unwrap());
+
+    // The prefix is fixed once the bucket has been created
+    assert!(store
+        .prefixed_bucket::<String, String>(Some("urls"), b"other")
+        .is_err());
+    assert!(store
+        .prefixed_bucket::<String, String>(Some("urls"), prefix.as_bytes())
+        .is_ok());
+}
+
+#[test]
+fn test_apply_batch_if() {
+    let path = reset("apply_batch_if");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<&str, String>(Some("test")).unwrap();
+
+    bucket.set(&"state", &"open".to_string()).unwrap();
+    bucket.set(&"b", &"old".to_string()).unwrap();
+
+    let mut batch = Batch::new();
+    batch.set(&"a", &"1".to_string()).unwrap();
+    batch.remove(&"b").unwrap();
+    batch.set(&"state", &"closed".to_string()).unwrap();
+
+    let closed = "closed".to_string();
+    assert!(!bucket
+        .apply_batch_if(batch.clone(), &"state", Some(&closed))
+        .unwrap());
+    assert!(!bucket
+        .apply_batch_if(batch.clone(), &"state", None)
+        .unwrap());
+    assert_eq!(bucket.get(&"a").unwrap(), None);
+    assert_eq!(bucket.get(&"b").unwrap(), Some("old".to_string()));
+
+    let open = "open".to_string();
+    assert!(bucket
+        .apply_batch_if(batch.clone(), &"state", Some(&open))
+        .unwrap());
+    assert_eq!(bucket.get(&"a").unwrap(), Some("1".to_string()));
+    assert_eq!(bucket.get(&"b").unwrap(), None);
+    assert_eq!(bucket.get(&"state").unwrap(), Some(closed));
+
+    // The condition no longer holds once the batch has been applied
+    assert!(!bucket.apply_batch_if(batch, &"state", Some(&open)).unwrap());
+    assert!(bucket
+        .apply_batch_if(Batch::new(), &"missing", None)
+        .unwrap());
+}
+
+#[test]
+fn test_join() {
+    let path = reset("join");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let users = store.bucket::<String, String>(Some("users")).unwrap();
+    let scores = store.bucket::<String, u64>(Some("scores")).unwrap();
+
+    users
+        .set(&"alice".to_string(), &"Alice".to_string())
+        .unwrap();
+    users.set(&"bob".to_string(), &"Bob".to_string()).unwrap();
+    users.set(&"dave".to_string(), &"Dave".to_string()).unwrap();
+    scores.set(&"bob".to_string(), &7).unwrap();
+    scores.set(&"carol".to_string(), &3).unwrap();
+    scores.set(&"dave".to_string(), &9).unwrap();
+    scores.set(&"erin".to_string(), &1).unwrap();
+
+    let rows = users.join(&scores).collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(
+        rows,
+        vec![
+            ("alice".to_string(), Some("Alice".to_string()), None),
+            ("bob".to_string(), Some("Bob".to_string()), Some(7)),
+            ("carol".to_string(), None, Some(3)),
+            ("dave".to_string(), Some("Dave".to_string()), Some(9)),
+            ("erin".to_string(), None, Some(1)),
+        ]
+    );
+
+    let inner = rows
+        .iter()
+        .filter(|(_, a, b)| a.is_some() && b.is_some())
+        .map(|(k, _, _)| k.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(inner, vec!["bob", "dave"]);
+
+    let empty = store.bucket::<String, u64>(Some("empty")).unwrap();
+    assert_eq!(users.join(&empty).count(), 3);
+    assert_eq!(empty.join(&scores).count(), 4);
+}
+
+#[cfg(feature = "bincode-value")]
+#[test]
+fn test_tagged_schema() {
+    use crate::Bincode;
+    let path = reset("tagged_schema");
+
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+    struct UserV1 {
+        name: String,
+    }
+
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+    struct UserV2 {
+        name: String,
+        age: Option<u32>,
+    }
+
+    impl Schema for Bincode<UserV1> {
+        const VERSION: u8 = 1;
+    }
+
+    fn decode_v1(r: Raw) -> Result<Bincode<UserV2>, Error> {
+        let v1 = Bincode::<UserV1>::from_raw_value(r)?.0;
+        Ok(Bincode(UserV2 {
+            name: v1.name,
+            age: None,
+        }))
+    }
+
+    impl Schema for Bincode<UserV2> {
+        const VERSION: u8 = 2;
+        const DECODERS: &'static [(u8, DecodeFn<Self>)] = &[(1, decode_v1)];
+    }
+
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+
+    let v1 = store
+        .bucket::<&str, Tagged<Bincode<UserV1>>>(Some("users"))
+        .unwrap();
+    v1.set(&"old", &Tagged(Bincode(UserV1 { name: "Old".into() })))
+        .unwrap();
+
+    let v2 = store
+        .bucket::<&str, Tagged<Bincode<UserV2>>>(Some("users"))
+        .unwrap();
+    v2.set(
+        &"new",
+        &Tagged(Bincode(UserV2 {
+            name: "New".into(),
+            age: Some(30),
+        })),
+    )
+    .unwrap();
+
+    // The v1 value is read using the registered decoder
+    let old = v2.get(&"old").unwrap().unwrap().0 .0;
+    assert_eq!(
+        old,
+        UserV2 {
+            name: "Old".into(),
+            age: None
+        }
+    );
+    let new = v2.get(&"new").unwrap().unwrap().0 .0;
+    assert_eq!(new.age, Some(30));
+
+    // v1 has no decoder for the newer version
+    assert!(v1.get(&"new").is_err());
+    assert_eq!(v1.get(&"old").unwrap().unwrap().0 .0.name, "Old");
+}
+
+#[test]
+fn test_store_transaction() {
+    let path = reset("store_transaction");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+
+    // Bucket names built at runtime
+    let names = ["a", "b"]
+        .iter()
+        .map(|s| format!("accounts-{}", s))
+        .collect::<Vec<_>>();
+    let names = names.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+    let a = store.bucket::<&str, String>(Some(names[0])).unwrap();
+    a.set(&"balance", &"10".to_string()).unwrap();
+
+    store
+        .transaction(&names, |t| {
+            let balance = t[0].get(&Raw::from(b"balance"))?.unwrap();
+            t[0].remove(&Raw::from(b"balance"))?;
+            t[1].set(&Raw::from(b"balance"), &balance)?;
+            Ok::<_, TransactionError<Error>>(())
+        })
+        .unwrap();
+
+    let b = store.bucket::<&str, String>(Some(names[1])).unwrap();
+    assert_eq!(a.get(&"balance").unwrap(), None);
+    assert_eq!(b.get(&"balance").unwrap(), Some("10".to_string()));
+
+    // Aborting rolls back the writes made to every bucket
+    let result = store.transaction(&names, |t| {
+        t[0].set(&Raw::from(b"balance"), &Raw::from(b"20"))?;
+        t[1].remove(&Raw::from(b"balance"))?;
+        Err::<(), _>(abort(Error::Message("rollback".into())))
+    });
+    assert!(matches!(result, Err(Error::Message(_))));
+    assert_eq!(a.get(&"balance").unwrap(), None);
+    assert_eq!(b.get(&"balance").unwrap(), Some("10".to_string()));
+
+    assert!(store
+        .transaction(&[names[0], names[0]], |_| Ok::<_, TransactionError<Error>>(
+            ()
+        ))
+        .is_err());
+
+    let result = store.transaction(&[], |_| Ok::<_, TransactionError<Error>>(()));
+    assert!(matches!(result, Err(Error::Message(_))));
+}
+
+#[test]
+fn test_iter_from() {
+    let path = reset("iter_from");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<Integer, u64>(None).unwrap();
+
+    for i in 0..10u64 {
+        bucket.set(&Integer::from(i), &i).unwrap();
+    }
+
+    let values = |inclusive| {
+        bucket
+            .iter_from(&Integer::from(7u64), inclusive)
+            .map(|item| item.unwrap().value::<u64>().unwrap())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(values(true), vec![7, 8, 9]);
+    assert_eq!(values(false), vec![8, 9]);
+
+    // Missing start keys begin at the next key either way
+    bucket.remove(&Integer::from(7u64)).unwrap();
+    assert_eq!(values(true), vec![8, 9]);
+    assert_eq!(values(false), vec![8, 9]);
+    assert_eq!(bucket.iter_from(&Integer::from(9u64), false).count(), 0);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_flush_all_async() {
+    let path = reset("flush_all_async");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+
+    for i in 0..4 {
+        let bucket = store
+            .bucket::<Integer, String>(Some(&format!("bucket{}", i)))
+            .unwrap();
+        for j in 0..100u64 {
+            bucket.set(&Integer::from(j), &"x".repeat(64)).unwrap();
+        }
+    }
+
+    assert!(store.flush_all_async().await.unwrap() > 0);
+    // Nothing is left to flush
+    assert_eq!(store.flush_all_async().await.unwrap(), 0);
+}
+
+#[test]
+fn test_decoded_size_hint() {
+    let path = reset("decoded_size_hint");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+
+    let s = "hello world".to_string();
+    assert_eq!(
+        s.decoded_size_hint(),
+        std::mem::size_of::<String>() + s.capacity()
+    );
+    let bytes = vec![0u8; 100];
+    assert_eq!(
+        bytes.decoded_size_hint(),
+        std::mem::size_of::<Vec<u8>>() + 100
+    );
+    assert_eq!(7u64.decoded_size_hint(), std::mem::size_of::<u64>());
+    assert_eq!(Raw::from(b"abc").decoded_size_hint(), 3);
+
+    #[cfg(feature = "json-value")]
+    {
+        let v = Json(vec![1u32, 2, 3]);
+        assert_eq!(
+            v.decoded_size_hint(),
+            std::mem::size_of::<Vec<u32>>() + "[1,2,3]".len()
+        );
+    }
+
+    let bucket = store.bucket::<&str, String>(None).unwrap();
+    bucket.set(&"user/a", &"x".repeat(10)).unwrap();
+    bucket.set(&"user/b", &"x".repeat(20)).unwrap();
+    bucket.set(&"other", &"x".repeat(1000)).unwrap();
+    assert_eq!(
+        bucket.estimated_memory_prefix(&"user/").unwrap(),
+        2 * std::mem::size_of::<String>() + 30
+    );
+    assert_eq!(bucket.estimated_memory_prefix(&"missing").unwrap(), 0);
+}
+
+#[test]
+fn test_update_and_fetch() {
+    let path = reset("update_and_fetch");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+    let bucket = store.bucket::<Integer, u64>(None).unwrap();
+    let key = Integer::from(1u64);
+
+    let threads = (0..8)
+        .map(|_| {
+            let bucket = bucket.clone();
+            std::thread::spawn(move || {
+                for _ in 0..100 {
+                    bucket
+                        .update_and_fetch(&key, |v| Some(v.unwrap_or(0) + 1))
+                        .unwrap();
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for t in threads {
+        t.join().unwrap();
+    }
+    assert_eq!(bucket.get(&key).unwrap(), Some(800));
+
+    assert_eq!(
+        bucket.update_and_fetch(&key, |v| v.map(|v| v * 2)).unwrap(),
+        Some(1600)
+    );
+    assert_eq!(bucket.update_and_fetch(&key, |_| None).unwrap(), None);
+    assert!(!bucket.contains(&key).unwrap());
+
+    // Decoding errors are returned and the stored value is left unchanged
+    let raw = store.bucket::<Integer, Raw>(None).unwrap();
+    raw.set(&key, &Raw::from(b"bad")).unwrap();
+    assert!(bucket.update_and_fetch(&key, |_| Some(1)).is_err());
+    assert_eq!(raw.get(&key).unwrap(), Some(Raw::from(b"bad")));
+}
+
+#[test]
+fn test_reserved_trees_hidden() {
+    let path = reset("reserved_trees_hidden");
+    let cfg = Config::new(path.clone());
+    let store = Store::new(cfg).unwrap();
+
+    let counted = store
+        .counted_bucket::<&str, String>(Some("counted"))
+        .unwrap();
+    counted.set(&"a", &"1".to_string()).unwrap();
+    let prefixed = store
+        .prefixed_bucket::<String, String>(Some("prefixed"), b"long/prefix/")
+        .unwrap();
+    prefixed
+        .set(&"long/prefix/a".to_string(), &"1".to_string())
+        .unwrap();
+    Migrator::new()
+        .step(|store| {
+            store.bucket::<&str, String>(Some("users"))?;
+            Ok(())
+        })
+        .run(&store)
+        .unwrap();
+
+    let buckets = store.buckets();
+    assert!(buckets.contains(&"counted".to_string()));
+    assert!(buckets.contains(&"prefixed".to_string()));
+    assert!(buckets.contains(&"users".to_string()));
+    assert!(buckets.iter().all(|name| !name.starts_with("__kv__")));
+    assert_eq!(store.info().unwrap().bucket_count, buckets.len());
+    assert!(store
+        .bucket_sizes()
+        .unwrap()
+        .keys()
+        .all(|name| !name.starts_with("__kv__")));
+
+    let mut stream = Vec::new();
+    store.export_stream(&mut stream).unwrap();
+    assert!(!stream.windows(6).any(|w| w == b"__kv__"));
+
+    let mut stable = Vec::new();
+    store.export_stable(&mut stable).unwrap();
+    let sections = String::from_utf8(stable)
+        .unwrap()
+        .lines()
+        .filter(|line| line.starts_with('['))
+        .count();
+    assert_eq!(sections, buckets.len());
+}
+
+#[test]
+fn test_merge_from_skips_reserved_trees() {
+    let one = Store::new(Config::new(reset("merge_reserved_one"))).unwrap();
+    let two = Store::new(Config::new(reset("merge_reserved_two"))).unwrap();
+
+    let migrator = |n: usize| {
+        let mut m = Migrator::new();
+        for _ in 0..n {
+            m = m.step(|_| Ok(()));
+        }
+        m
+    };
+    migrator(1).run(&one).unwrap();
+    migrator(3).run(&two).unwrap();
+
+    let counted = two.counted_bucket::<&str, String>(Some("data")).unwrap();
+    counted.set(&"a", &"1".to_string()).unwrap();
+    counted.set(&"b", &"2".to_string()).unwrap();
+
+    let report = one.merge_from(&two, MergePolicy::Overwrite).unwrap();
+    assert_eq!(report.inserted, 2);
+    assert!(report.conflicts.is_empty());
+
+    // The destination keeps its own migration version
+    assert_eq!(Migrator::version(&one).unwrap(), 1);
+    let data = one.bucket::<&str, String>(Some("data")).unwrap();
+    assert_eq!(data.len(), 2);
+}
@@ -1,14 +1,61 @@
+use std::cell::RefCell;
 use std::marker::PhantomData;
 
-use crate::{Batch, Error, Key, Value};
+use crate::{Batch, Error, Event, Item, Key, Raw, Value};
 
 /// Transaction error
 pub type TransactionError<E> = sled::transaction::ConflictableTransactionError<E>;
 
+/// A write applied by a committed transaction, see `Bucket::transaction_with_writes`
+pub struct Write {
+    /// Name of the bucket that was written to
+    pub bucket: Raw,
+
+    /// The key that was set or removed
+    pub event: Event<Raw, Raw>,
+}
+
+pub(crate) struct Recorder {
+    bucket: Raw,
+    writes: RefCell<Vec<Write>>,
+    ids: RefCell<Vec<u64>>,
+}
+
+impl Recorder {
+    pub(crate) fn new(bucket: Raw) -> Recorder {
+        Recorder {
+            bucket,
+            writes: RefCell::new(Vec::new()),
+            ids: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn clear(&self) {
+        self.writes.borrow_mut().clear();
+        self.ids.borrow_mut().clear();
+    }
+
+    pub(crate) fn into_writes(self) -> Vec<Write> {
+        self.writes.into_inner()
+    }
+
+    pub(crate) fn into_ids(self) -> Vec<u64> {
+        self.ids.into_inner()
+    }
+
+    fn push(&self, event: Event<Raw, Raw>) {
+        self.writes.borrow_mut().push(Write {
+            bucket: self.bucket.clone(),
+            event,
+        })
+    }
+}
+
 /// Transaction
 #[derive(Clone)]
 pub struct Transaction<'a, 'b, K: Key<'a>, V: Value>(
     &'b sled::transaction::TransactionalTree,
+    Option<&'b Recorder>,
     PhantomData<K>,
     PhantomData<V>,
     PhantomData<&'a ()>,
@@ -16,10 +63,21 @@ pub struct Transaction<'a, 'b, K: Key<'a>, V: Value>(
 
 impl<'a, 'b, K: Key<'a>, V: Value> Transaction<'a, 'b, K, V> {
     pub(crate) fn new(t: &'b sled::transaction::TransactionalTree) -> Self {
-        Transaction(t, PhantomData, PhantomData, PhantomData)
+        Transaction(t, None, PhantomData, PhantomData, PhantomData)
+    }
+
+    pub(crate) fn recorded(
+        t: &'b sled::transaction::TransactionalTree,
+        recorder: &'b Recorder,
+    ) -> Self {
+        Transaction(t, Some(recorder), PhantomData, PhantomData, PhantomData)
     }
 
     /// Get the value associated with the specified key
+    ///
+    /// sled 0.34 runs transactions one at a time under a process-wide lock, so no other write can
+    /// change the value before this transaction commits and a read-modify-write using `get` and
+    /// `set` is never retried.
     pub fn get(&self, key: &K) -> Result<Option<V>, TransactionError<Error>> {
         let v = self
             .0
@@ -41,33 +99,68 @@ impl<'a, 'b, K: Key<'a>, V: Value> Transaction<'a, 'b, K, V> {
 
     /// Set the value associated with the specified key to the provided value
     pub fn set(&self, key: &K, value: &V) -> Result<Option<V>, TransactionError<Error>> {
+        let k = key.to_raw_key().map_err(TransactionError::Abort)?;
         let v = value.to_raw_value().map_err(TransactionError::Abort)?;
-        Ok(self
-            .0
-            .insert(key.to_raw_key().map_err(TransactionError::Abort)?, v)?
+        if let Some(r) = self.1 {
+            r.push(Event::Set(Item(
+                k.clone(),
+                v.clone(),
+                PhantomData,
+                PhantomData,
+            )));
+        }
+        self.0
+            .insert(k, v)?
             .map(|x| V::from_raw_value(x).map_err(TransactionError::Abort))
             // https://users.rust-lang.org/t/convenience-method-for-flipping-option-result-to-result-option/13695/7
-            .map_or(Ok(None), |v| v.map(Some))?)
+            .map_or(Ok(None), |v| v.map(Some))
     }
 
     /// Remove the value associated with the specified key from the database
     pub fn remove(&self, key: &K) -> Result<Option<V>, TransactionError<Error>> {
-        Ok(self
-            .0
-            .remove(key.to_raw_key().map_err(TransactionError::Abort)?)?
+        let k = key.to_raw_key().map_err(TransactionError::Abort)?;
+        if let Some(r) = self.1 {
+            r.push(Event::Remove(k.clone()));
+        }
+        self.0
+            .remove(k)?
             .map(|x| V::from_raw_value(x).map_err(TransactionError::Abort))
             // https://users.rust-lang.org/t/convenience-method-for-flipping-option-result-to-result-option/13695/7
-            .map_or(Ok(None), |v| v.map(Some))?)
+            .map_or(Ok(None), |v| v.map(Some))
+    }
+
+    /// Read the value associated with the specified key, transform it using `f` and write the
+    /// result back, removing the key if `f` returns `None`. Returns the new value
+    pub fn update(
+        &self,
+        key: &K,
+        f: impl FnOnce(Option<V>) -> Option<V>,
+    ) -> Result<Option<V>, TransactionError<Error>> {
+        let value = f(self.get(key)?);
+        match &value {
+            Some(v) => {
+                self.set(key, v)?;
+            }
+            None => {
+                self.remove(key)?;
+            }
+        }
+        Ok(value)
     }
 
-    /// Apply batch update
+    /// Apply batch update, writes applied using a batch are not recorded by
+    /// `Bucket::transaction_with_writes`
     pub fn batch(&self, batch: &Batch<K, V>) -> Result<(), TransactionError<Error>> {
-        self.0.apply_batch(&batch.0)?;
+        self.0.apply_batch(&batch.to_sled())?;
         Ok(())
     }
 
     /// Generate a monotonic ID. Not guaranteed to be contiguous or idempotent, can produce different values in the same transaction in case of conflicts
     pub fn generate_id(&self) -> Result<u64, TransactionError<Error>> {
-        Ok(self.0.generate_id()?)
+        let id = self.0.generate_id()?;
+        if let Some(r) = self.1 {
+            r.ids.borrow_mut().push(id);
+        }
+        Ok(id)
     }
 }
@@ -1,3 +1,8 @@
+use std::marker::PhantomData;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::ops::{Range, RangeInclusive};
+use std::time::{Duration, SystemTime};
+
 use crate::Error;
 
 /// A trait used to convert between types and `Raw`
@@ -7,11 +12,27 @@ pub trait Value: Sized {
 
     /// Convert from Raw
     fn from_raw_value(r: Raw) -> Result<Self, Error>;
+
+    /// Estimate the number of bytes used by the decoded value in memory, see
+    /// `Bucket::estimated_memory_prefix`. Defaults to the length of the encoded value
+    fn decoded_size_hint(&self) -> usize {
+        self.to_raw_value().map(|r| r.len()).unwrap_or(0)
+    }
 }
 
 /// Raw is an alias for `sled::IVec`
 pub type Raw = sled::IVec;
 
+/// Create a `Raw` from a static slice
+///
+/// Short slices, up to 22 bytes in sled 0.34, are stored inline, so no allocation is performed.
+/// This makes short constant keys cheap to construct in hot loops. Longer slices are copied into
+/// a new allocation, because `sled::IVec` is unable to borrow static data. The inline limit is
+/// private to sled and may change in other versions.
+pub fn raw_static(x: &'static [u8]) -> Raw {
+    Raw::from(x)
+}
+
 impl Value for Raw {
     fn to_raw_value(&self) -> Result<Raw, Error> {
         Ok(self.clone())
@@ -40,6 +61,22 @@ impl Value for Vec<u8> {
     fn from_raw_value(r: Raw) -> Result<Self, Error> {
         Ok(r.to_vec())
     }
+
+    fn decoded_size_hint(&self) -> usize {
+        std::mem::size_of::<Self>() + self.capacity()
+    }
+}
+
+/// `Bytes` values share the underlying buffer with `Raw` when decoding rather than copying
+#[cfg(feature = "bytes")]
+impl Value for bytes::Bytes {
+    fn to_raw_value(&self) -> Result<Raw, Error> {
+        Ok(self.as_ref().into())
+    }
+
+    fn from_raw_value(r: Raw) -> Result<Self, Error> {
+        Ok(bytes::Bytes::from_owner(r))
+    }
 }
 
 impl Value for String {
@@ -48,7 +85,501 @@ impl Value for String {
     }
 
     fn from_raw_value(r: Raw) -> Result<Self, Error> {
-        let x = r.to_vec();
-        Ok(String::from_utf8(x)?)
+        Ok(crate::error::utf8(&r)?.to_string())
+    }
+
+    fn decoded_size_hint(&self) -> usize {
+        std::mem::size_of::<Self>() + self.capacity()
+    }
+}
+
+fn duration_from_nanos(r: &Raw) -> Result<Duration, Error> {
+    let buf: [u8; 16] = r.as_ref().try_into().map_err(|_| Error::InvalidLength {
+        expected: 16,
+        found: r.len(),
+    })?;
+    let nanos = u128::from_be_bytes(buf);
+    let secs = u64::try_from(nanos / 1_000_000_000)
+        .map_err(|_| Error::Message("Duration out of range".into()))?;
+    Ok(Duration::new(secs, (nanos % 1_000_000_000) as u32))
+}
+
+/// `Duration` values are encoded as 16 big-endian bytes containing the number of nanoseconds
+impl Value for Duration {
+    fn to_raw_value(&self) -> Result<Raw, Error> {
+        Ok(self.as_nanos().to_be_bytes().as_ref().into())
+    }
+
+    fn from_raw_value(r: Raw) -> Result<Self, Error> {
+        duration_from_nanos(&r)
+    }
+}
+
+/// `SystemTime` values are encoded as 16 big-endian bytes containing the number of nanoseconds
+/// since the Unix epoch, times before the epoch cannot be stored
+impl Value for SystemTime {
+    fn to_raw_value(&self) -> Result<Raw, Error> {
+        self.duration_since(SystemTime::UNIX_EPOCH)?.to_raw_value()
+    }
+
+    fn from_raw_value(r: Raw) -> Result<Self, Error> {
+        let d = duration_from_nanos(&r)?;
+        SystemTime::UNIX_EPOCH
+            .checked_add(d)
+            .ok_or_else(|| Error::Message("SystemTime out of range".into()))
+    }
+}
+
+fn array<const N: usize>(r: &[u8]) -> Result<[u8; N], Error> {
+    r.try_into().map_err(|_| Error::InvalidLength {
+        expected: N,
+        found: r.len(),
+    })
+}
+
+/// `u64` values are encoded as 8 big-endian bytes
+impl Value for u64 {
+    fn to_raw_value(&self) -> Result<Raw, Error> {
+        Ok(self.to_be_bytes().as_ref().into())
+    }
+
+    fn from_raw_value(r: Raw) -> Result<Self, Error> {
+        Ok(u64::from_be_bytes(array(&r)?))
+    }
+}
+
+fn decode_bounds(r: &[u8]) -> Result<(u64, u64), Error> {
+    let buf = array::<16>(r)?;
+    let (a, b) = buf.split_at(8);
+    Ok((u64::from_be_bytes(array(a)?), u64::from_be_bytes(array(b)?)))
+}
+
+fn encode_bounds(a: u64, b: u64) -> Raw {
+    let mut buf = [0u8; 16];
+    buf[..8].copy_from_slice(&a.to_be_bytes());
+    buf[8..].copy_from_slice(&b.to_be_bytes());
+    buf.as_ref().into()
+}
+
+/// `Range<u64>` values are encoded as the start and end as 8 big-endian bytes each
+impl Value for Range<u64> {
+    fn to_raw_value(&self) -> Result<Raw, Error> {
+        Ok(encode_bounds(self.start, self.end))
+    }
+
+    fn from_raw_value(r: Raw) -> Result<Self, Error> {
+        let (start, end) = decode_bounds(&r)?;
+        Ok(start..end)
+    }
+}
+
+/// `RangeInclusive<u64>` values are encoded as the start and end as 8 big-endian bytes each
+///
+/// A range that has been exhausted by iteration is stored with its current bounds, so it is no
+/// longer empty once it has been read back.
+impl Value for RangeInclusive<u64> {
+    fn to_raw_value(&self) -> Result<Raw, Error> {
+        Ok(encode_bounds(*self.start(), *self.end()))
+    }
+
+    fn from_raw_value(r: Raw) -> Result<Self, Error> {
+        let (start, end) = decode_bounds(&r)?;
+        Ok(start..=end)
+    }
+}
+
+/// `Ipv4Addr` values are encoded as their 4 octets
+impl Value for Ipv4Addr {
+    fn to_raw_value(&self) -> Result<Raw, Error> {
+        Ok(self.octets().as_ref().into())
+    }
+
+    fn from_raw_value(r: Raw) -> Result<Self, Error> {
+        Ok(Ipv4Addr::from(array::<4>(&r)?))
+    }
+}
+
+/// `Ipv6Addr` values are encoded as their 16 octets
+impl Value for Ipv6Addr {
+    fn to_raw_value(&self) -> Result<Raw, Error> {
+        Ok(self.octets().as_ref().into())
+    }
+
+    fn from_raw_value(r: Raw) -> Result<Self, Error> {
+        Ok(Ipv6Addr::from(array::<16>(&r)?))
+    }
+}
+
+fn encode_ip(ip: &IpAddr, buf: &mut Vec<u8>) {
+    match ip {
+        IpAddr::V4(ip) => {
+            buf.push(4);
+            buf.extend_from_slice(&ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            buf.push(6);
+            buf.extend_from_slice(&ip.octets());
+        }
+    }
+}
+
+fn decode_ip(r: &[u8]) -> Result<(IpAddr, &[u8]), Error> {
+    let len = match r.first() {
+        Some(4) => 4,
+        Some(6) => 16,
+        Some(_) => return Err(Error::Message("Invalid IP address tag".into())),
+        None => {
+            return Err(Error::InvalidLength {
+                expected: 5,
+                found: 0,
+            })
+        }
+    };
+
+    if r.len() < len + 1 {
+        return Err(Error::InvalidLength {
+            expected: len + 1,
+            found: r.len(),
+        });
+    }
+
+    let ip = if len == 4 {
+        IpAddr::V4(Ipv4Addr::from(array::<4>(&r[1..5])?))
+    } else {
+        IpAddr::V6(Ipv6Addr::from(array::<16>(&r[1..17])?))
+    };
+    Ok((ip, &r[len + 1..]))
+}
+
+/// `IpAddr` values are encoded as a tag byte, `4` or `6`, followed by the address octets
+impl Value for IpAddr {
+    fn to_raw_value(&self) -> Result<Raw, Error> {
+        let mut buf = Vec::with_capacity(17);
+        encode_ip(self, &mut buf);
+        Ok(buf.into())
+    }
+
+    fn from_raw_value(r: Raw) -> Result<Self, Error> {
+        let (ip, rest) = decode_ip(&r)?;
+        if !rest.is_empty() {
+            return Err(Error::InvalidLength {
+                expected: r.len() - rest.len(),
+                found: r.len(),
+            });
+        }
+        Ok(ip)
+    }
+}
+
+/// `SocketAddr` values are encoded like `IpAddr` followed by 2 big-endian port bytes
+impl Value for SocketAddr {
+    fn to_raw_value(&self) -> Result<Raw, Error> {
+        let mut buf = Vec::with_capacity(19);
+        encode_ip(&self.ip(), &mut buf);
+        buf.extend_from_slice(&self.port().to_be_bytes());
+        Ok(buf.into())
+    }
+
+    fn from_raw_value(r: Raw) -> Result<Self, Error> {
+        let (ip, rest) = decode_ip(&r)?;
+        let port = array::<2>(rest).map_err(|_| Error::InvalidLength {
+            expected: r.len() - rest.len() + 2,
+            found: r.len(),
+        })?;
+        Ok(SocketAddr::new(ip, u16::from_be_bytes(port)))
+    }
+}
+
+fn split_tag(r: &Raw) -> Result<(u8, Raw), Error> {
+    match r.first() {
+        Some(tag) => Ok((*tag, r.subslice(1, r.len() - 1))),
+        None => Err(Error::InvalidLength {
+            expected: 1,
+            found: 0,
+        }),
+    }
+}
+
+fn tagged(tag: u8, value: Option<Raw>) -> Raw {
+    let len = value.as_ref().map_or(0, |v| v.len());
+    let mut buf = Vec::with_capacity(1 + len);
+    buf.push(tag);
+    if let Some(value) = value {
+        buf.extend_from_slice(&value);
+    }
+    buf.into()
+}
+
+/// `Option` values are encoded as a tag byte, `0` for `None` or `1` for `Some` followed by the
+/// inner value, so a stored `None` can be distinguished from a missing key
+impl<V: Value> Value for Option<V> {
+    fn to_raw_value(&self) -> Result<Raw, Error> {
+        match self {
+            None => Ok(tagged(0, None)),
+            Some(v) => Ok(tagged(1, Some(v.to_raw_value()?))),
+        }
+    }
+
+    fn from_raw_value(r: Raw) -> Result<Self, Error> {
+        match split_tag(&r)? {
+            (0, rest) if rest.is_empty() => Ok(None),
+            (1, rest) => Ok(Some(V::from_raw_value(rest)?)),
+            _ => Err(Error::Message("Invalid Option value".into())),
+        }
+    }
+}
+
+/// `Result` values are encoded as a tag byte, `0` for `Err` or `1` for `Ok` followed by the
+/// inner value
+impl<V: Value, E: Value> Value for Result<V, E> {
+    fn to_raw_value(&self) -> Result<Raw, Error> {
+        match self {
+            Err(e) => Ok(tagged(0, Some(e.to_raw_value()?))),
+            Ok(v) => Ok(tagged(1, Some(v.to_raw_value()?))),
+        }
+    }
+
+    fn from_raw_value(r: Raw) -> Result<Self, Error> {
+        match split_tag(&r)? {
+            (0, rest) => Ok(Err(E::from_raw_value(rest)?)),
+            (1, rest) => Ok(Ok(V::from_raw_value(rest)?)),
+            _ => Err(Error::Message("Invalid Result value".into())),
+        }
+    }
+}
+
+fn read_field(r: &Raw, offset: &mut usize) -> Result<Raw, Error> {
+    let rest = &r[*offset..];
+    let len = u32::from_be_bytes(array(rest.get(..4).unwrap_or(rest))?) as usize;
+    if rest.len() < 4 + len {
+        return Err(Error::InvalidLength {
+            expected: *offset + 4 + len,
+            found: r.len(),
+        });
+    }
+    let field = r.subslice(*offset + 4, len);
+    *offset += 4 + len;
+    Ok(field)
+}
+
+macro_rules! tuple_value {
+    ($($t:ident),*) => {
+        /// Tuples are encoded as the concatenation of their elements, each prefixed with its
+        /// length as 4 big-endian bytes. The encoding is unambiguous, but stored tuples are not
+        /// ordered by their elements
+        impl<$($t: Value),*> Value for ($($t,)*) {
+            #[allow(non_snake_case)]
+            fn to_raw_value(&self) -> Result<Raw, Error> {
+                let ($($t,)*) = self;
+                let mut buf = Vec::new();
+                $(
+                    let field = $t.to_raw_value()?;
+                    let len = u32::try_from(field.len())
+                        .map_err(|_| Error::Message("Tuple element too large".into()))?;
+                    buf.extend_from_slice(&len.to_be_bytes());
+                    buf.extend_from_slice(&field);
+                )*
+                Ok(buf.into())
+            }
+
+            fn from_raw_value(r: Raw) -> Result<Self, Error> {
+                let mut offset = 0;
+                let value = ($($t::from_raw_value(read_field(&r, &mut offset)?)?,)*);
+                if offset != r.len() {
+                    return Err(Error::InvalidLength {
+                        expected: offset,
+                        found: r.len(),
+                    });
+                }
+                Ok(value)
+            }
+        }
+    };
+}
+
+tuple_value!(A, B);
+tuple_value!(A, B, C);
+
+/// Conversion between a small enum and its discriminant, used by `Enum8`
+pub trait ByteEnum: Sized {
+    /// Get the discriminant of the value
+    fn to_u8(&self) -> u8;
+
+    /// Get the value for a discriminant, or `None` if it is invalid
+    fn from_u8(x: u8) -> Option<Self>;
+}
+
+/// A value stored as a single discriminant byte, for enums without fields that don't need a
+/// serde codec
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Enum8<T>(pub T);
+
+impl<T: ByteEnum> Value for Enum8<T> {
+    fn to_raw_value(&self) -> Result<Raw, Error> {
+        Ok([self.0.to_u8()].as_ref().into())
+    }
+
+    fn from_raw_value(r: Raw) -> Result<Self, Error> {
+        let [x] = array::<1>(&r)?;
+        T::from_u8(x)
+            .map(Enum8)
+            .ok_or_else(|| Error::Message(format!("Invalid enum discriminant: {}", x)))
+    }
+}
+
+/// Decoder for a previous encoding of a `Schema` value
+pub type DecodeFn<T> = fn(Raw) -> Result<T, Error>;
+
+/// A value with a versioned encoding, used by `Tagged`
+///
+/// The `Value` implementation is the current encoding, which is written with the `VERSION` tag.
+/// Values written using older encodings are decoded using the matching entry in `DECODERS`.
+pub trait Schema: Value + 'static {
+    /// Version of the current encoding
+    const VERSION: u8;
+
+    /// Decoders for previous encodings, paired with their version
+    const DECODERS: &'static [(u8, DecodeFn<Self>)] = &[];
+}
+
+/// A value stored with a 1-byte schema version, so values written using older encodings can
+/// still be read after the encoding changes, see `Schema`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tagged<T>(pub T);
+
+impl<T: Schema> Value for Tagged<T> {
+    fn to_raw_value(&self) -> Result<Raw, Error> {
+        let value = self.0.to_raw_value()?;
+        let mut buf = Vec::with_capacity(value.len() + 1);
+        buf.push(T::VERSION);
+        buf.extend_from_slice(&value);
+        Ok(buf.into())
+    }
+
+    fn from_raw_value(r: Raw) -> Result<Self, Error> {
+        let version = match r.first() {
+            Some(&x) => x,
+            None => {
+                return Err(Error::InvalidLength {
+                    expected: 1,
+                    found: 0,
+                })
+            }
+        };
+        let value = r.subslice(1, r.len() - 1);
+        if version == T::VERSION {
+            return T::from_raw_value(value).map(Tagged);
+        }
+        match T::DECODERS.iter().find(|(v, _)| *v == version) {
+            Some((_, decode)) => decode(value).map(Tagged),
+            None => Err(Error::Message(format!(
+                "Unknown schema version: {}",
+                version
+            ))),
+        }
+    }
+}
+
+/// A value stored with a CRC32 checksum that is verified when it is read
+///
+/// The checksum is appended to the encoded value as 4 big-endian bytes, decoding fails with
+/// `Error::ChecksumMismatch` if the stored bytes have been corrupted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checked<V>(pub V);
+
+impl<V: Value> Value for Checked<V> {
+    fn to_raw_value(&self) -> Result<Raw, Error> {
+        let value = self.0.to_raw_value()?;
+        let mut buf = Vec::with_capacity(value.len() + 4);
+        buf.extend_from_slice(&value);
+        buf.extend_from_slice(&crc32fast::hash(&value).to_be_bytes());
+        Ok(buf.into())
+    }
+
+    fn from_raw_value(r: Raw) -> Result<Self, Error> {
+        if r.len() < 4 {
+            return Err(Error::InvalidLength {
+                expected: 4,
+                found: r.len(),
+            });
+        }
+        let n = r.len() - 4;
+        let expected = u32::from_be_bytes(array::<4>(&r[n..])?);
+        let found = crc32fast::hash(&r[..n]);
+        if expected != found {
+            return Err(Error::ChecksumMismatch { expected, found });
+        }
+        Ok(Checked(V::from_raw_value(r.subslice(0, n))?))
+    }
+}
+
+/// A value that is decoded on demand
+///
+/// Reading a `Deferred` value only keeps a reference to the stored bytes, the inner value is
+/// decoded each time `Deferred::get` is called. This makes iterating over a bucket cheap when
+/// only some of the values need to be inspected.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Deferred<V>(Raw, PhantomData<V>);
+
+impl<V: Value> Deferred<V> {
+    /// Encode a value
+    pub fn new(value: &V) -> Result<Deferred<V>, Error> {
+        Ok(Deferred(value.to_raw_value()?, PhantomData))
+    }
+
+    /// Decode the inner value
+    pub fn get(&self) -> Result<V, Error> {
+        V::from_raw_value(self.0.clone())
+    }
+
+    /// Get the encoded value
+    pub fn raw(&self) -> &Raw {
+        &self.0
+    }
+}
+
+impl<V: Value> Value for Deferred<V> {
+    fn to_raw_value(&self) -> Result<Raw, Error> {
+        Ok(self.0.clone())
+    }
+
+    fn from_raw_value(r: Raw) -> Result<Self, Error> {
+        Ok(Deferred(r, PhantomData))
+    }
+}
+
+/// A value paired with a version that is incremented on every write, see `Bucket::set_versioned`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Versioned<V> {
+    /// Version number
+    pub version: u64,
+
+    /// Inner value
+    pub value: V,
+}
+
+/// `Versioned` values are encoded as 8 big-endian version bytes followed by the inner value
+impl<V: Value> Value for Versioned<V> {
+    fn to_raw_value(&self) -> Result<Raw, Error> {
+        let value = self.value.to_raw_value()?;
+        let mut buf = Vec::with_capacity(8 + value.len());
+        buf.extend_from_slice(&self.version.to_be_bytes());
+        buf.extend_from_slice(&value);
+        Ok(buf.into())
+    }
+
+    fn from_raw_value(r: Raw) -> Result<Self, Error> {
+        if r.len() < 8 {
+            return Err(Error::InvalidLength {
+                expected: 8,
+                found: r.len(),
+            });
+        }
+        let mut version = [0u8; 8];
+        version.copy_from_slice(&r[..8]);
+        Ok(Versioned {
+            version: u64::from_be_bytes(version),
+            value: V::from_raw_value(r.subslice(8, r.len() - 8))?,
+        })
     }
 }
@@ -0,0 +1,97 @@
+use std::marker::PhantomData;
+
+use crate::{Bucket, Error, Key, Raw, Value};
+
+/// A bucket that keeps every version of the value stored for an id, see
+/// `Store::versioned_bucket`
+///
+/// Each version is stored under the raw id followed by the version number as 8 big-endian bytes,
+/// versions start at 1 and are ordered so the latest version of an id can be found using a
+/// single reverse range scan.
+#[derive(Clone)]
+pub struct VersionedBucket<'a, K: Key<'a>, V: Value> {
+    bucket: Bucket<'a, Raw, V>,
+    phantom: PhantomData<K>,
+}
+
+fn version_key(id: &[u8], version: u64) -> Raw {
+    let mut k = Vec::with_capacity(id.len() + 8);
+    k.extend_from_slice(id);
+    k.extend_from_slice(&version.to_be_bytes());
+    k.into()
+}
+
+fn decode_version(id: &[u8], k: &[u8]) -> Option<u64> {
+    // Keys of longer ids that start with `id` have a different length
+    if k.len() != id.len() + 8 || !k.starts_with(id) {
+        return None;
+    }
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(&k[id.len()..]);
+    Some(u64::from_be_bytes(buf))
+}
+
+impl<'a, K: Key<'a>, V: Value> VersionedBucket<'a, K, V> {
+    pub(crate) fn new(bucket: Bucket<'a, Raw, V>) -> Self {
+        VersionedBucket {
+            bucket,
+            phantom: PhantomData,
+        }
+    }
+
+    fn versions(&self, id: &[u8]) -> impl DoubleEndedIterator<Item = sled::Result<(Raw, Raw)>> {
+        self.bucket
+            .0
+            .range(version_key(id, 0)..=version_key(id, u64::MAX))
+    }
+
+    fn latest_raw(&self, id: &[u8]) -> Result<Option<(u64, Raw)>, Error> {
+        for kv in self.versions(id).rev() {
+            let (k, v) = kv?;
+            if let Some(version) = decode_version(id, &k) {
+                return Ok(Some((version, v)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Store a new version of the value for `id`, returning its version number
+    pub fn insert(&self, id: &K, value: &V) -> Result<u64, Error> {
+        let id = id.to_raw_key()?;
+        loop {
+            let version = match self.latest_raw(&id)? {
+                Some((version, _)) => version + 1,
+                None => 1,
+            };
+            if self.bucket.init_once(&version_key(&id, version), value)? {
+                return Ok(version);
+            }
+        }
+    }
+
+    /// Get the latest version of the value for `id` along with its version number
+    pub fn latest(&self, id: &K) -> Result<Option<(u64, V)>, Error> {
+        match self.latest_raw(&id.to_raw_key()?)? {
+            Some((version, v)) => Ok(Some((version, V::from_raw_value(v)?))),
+            None => Ok(None),
+        }
+    }
+
+    /// Get a specific version of the value for `id`
+    pub fn version(&self, id: &K, version: u64) -> Result<Option<V>, Error> {
+        self.bucket.get(&version_key(&id.to_raw_key()?, version))
+    }
+
+    /// Get every version of the value for `id`, oldest first
+    pub fn history(&self, id: &K) -> Result<Vec<(u64, V)>, Error> {
+        let id = id.to_raw_key()?;
+        let mut history = Vec::new();
+        for kv in self.versions(&id) {
+            let (k, v) = kv?;
+            if let Some(version) = decode_version(&id, &k) {
+                history.push((version, V::from_raw_value(v)?));
+            }
+        }
+        Ok(history)
+    }
+}
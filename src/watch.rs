@@ -0,0 +1,176 @@
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, TrySendError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::{Error, Event, Key, Raw};
+
+/// Summary of the changes received during one coalescing window
+#[derive(Debug, Clone)]
+pub struct ChangeSummary<K> {
+    /// Number of events received
+    pub count: usize,
+    min: Raw,
+    max: Raw,
+    phantom: PhantomData<K>,
+}
+
+impl<'a, K: Key<'a>> ChangeSummary<K> {
+    /// Smallest key that was changed
+    pub fn min_key(&'a self) -> Result<K, Error> {
+        K::from_raw_key(&self.min)
+    }
+
+    /// Largest key that was changed
+    pub fn max_key(&'a self) -> Result<K, Error> {
+        K::from_raw_key(&self.max)
+    }
+}
+
+/// Subscribe to key updates, coalescing events that arrive within a time window into a single
+/// `ChangeSummary`, see `Bucket::watch_prefix_coalesced`
+pub struct CoalescedWatch<K> {
+    subscriber: sled::Subscriber,
+    window: Duration,
+    phantom: PhantomData<K>,
+}
+
+fn event_key(event: &sled::Event) -> &Raw {
+    match event {
+        sled::Event::Insert { key, .. } | sled::Event::Remove { key } => key,
+    }
+}
+
+impl<K> CoalescedWatch<K> {
+    pub(crate) fn new(subscriber: sled::Subscriber, window: Duration) -> Self {
+        CoalescedWatch {
+            subscriber,
+            window,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<K> Iterator for CoalescedWatch<K> {
+    type Item = ChangeSummary<K>;
+
+    /// Blocks until an event is received, then collects events until `window` has elapsed
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = self.subscriber.next()?;
+        let deadline = Instant::now() + self.window;
+        let key = event_key(&first);
+        let mut summary = ChangeSummary {
+            count: 1,
+            min: key.clone(),
+            max: key.clone(),
+            phantom: PhantomData,
+        };
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+
+            match self.subscriber.next_timeout(remaining) {
+                Ok(event) => {
+                    let key = event_key(&event);
+                    if *key < summary.min {
+                        summary.min = key.clone();
+                    }
+                    if *key > summary.max {
+                        summary.max = key.clone();
+                    }
+                    summary.count += 1;
+                }
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        Some(summary)
+    }
+}
+
+/// What a `BufferedWatch` does with new events when its buffer is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// Discard the event and increment `BufferedWatch::dropped`
+    Drop,
+    /// Wait for the consumer to make room, buffering further events inside sled
+    Block,
+}
+
+/// Subscribe to key updates through a bounded buffer, see `Bucket::watch_prefix_buffered`
+///
+/// Events are forwarded from sled into the buffer by a background thread, which exits after the
+/// `BufferedWatch` is dropped once the next event is received.
+pub struct BufferedWatch<K, V> {
+    receiver: mpsc::Receiver<sled::Event>,
+    dropped: Arc<AtomicUsize>,
+    phantom: PhantomData<(K, V)>,
+}
+
+impl<K, V> BufferedWatch<K, V> {
+    pub(crate) fn new(
+        mut subscriber: sled::Subscriber,
+        capacity: usize,
+        overflow: Overflow,
+    ) -> Result<Self, Error> {
+        // A zero capacity channel would hand every event over directly, blocking the forwarding
+        // thread until it is received
+        if capacity == 0 {
+            return Err(Error::InvalidConfiguration);
+        }
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let counter = dropped.clone();
+        std::thread::Builder::new()
+            .name("kv-watch".into())
+            .spawn(move || {
+                for event in &mut subscriber {
+                    let sent = match overflow {
+                        Overflow::Block => sender.send(event).is_ok(),
+                        Overflow::Drop => match sender.try_send(event) {
+                            Ok(()) => true,
+                            Err(TrySendError::Full(_)) => {
+                                counter.fetch_add(1, Ordering::Relaxed);
+                                true
+                            }
+                            Err(TrySendError::Disconnected(_)) => false,
+                        },
+                    };
+                    if !sent {
+                        break;
+                    }
+                }
+            })?;
+        Ok(BufferedWatch {
+            receiver,
+            dropped,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Number of events that were discarded because the buffer was full
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Wait up to `timeout` for the next event
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<Event<K, V>> {
+        self.receiver
+            .recv_timeout(timeout)
+            .ok()
+            .map(Event::from_sled)
+    }
+}
+
+impl<K, V> Iterator for BufferedWatch<K, V> {
+    type Item = Event<K, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.recv().ok().map(Event::from_sled)
+    }
+}